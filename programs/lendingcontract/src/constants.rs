@@ -1,8 +1,20 @@
 use anchor_lang::prelude::*;
 
+// Exported in the IDL for clients setting a bank's feed through update_oracle_config
 #[constant]
+#[allow(dead_code)]
 pub const SOL_USD_FEED_ID:&str="0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
 #[constant]
+#[allow(dead_code)]
 pub const USDC_USD_FEED_ID:&str="0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
 #[constant]
-pub const MAX_AGE:u64=100;
\ No newline at end of file
+pub const MAX_AGE:u64=100;
+#[constant]
+pub const MAX_LOCK_DURATION:i64=4*365*24*60*60;
+// Passed as the amount to repay or withdraw to clear the whole accrued position
+#[constant]
+pub const FULL_BALANCE:u64=u64::MAX;
+// Passed as a fee proposal (type 4) parameter to leave that fee as it is, so zero can be set.
+// Other proposal types keep treating 0 as unchanged.
+#[constant]
+pub const PARAM_UNCHANGED:u64=u64::MAX;
@@ -1,19 +1,163 @@
 use anchor_lang::prelude::*;
 
+/// All program errors. Codes are stable and grouped by subsystem
+/// (Anchor adds 6000 to each discriminant):
+///
+/// - 6000-6099: core lending (deposit, withdraw, borrow, repay, liquidate)
+/// - 6100-6199: oracle
+/// - 6200-6299: admin, roles and bank configuration
+/// - 6300-6399: flash loans
+/// - 6400-6499: governance
+/// - 6500-6599: vote escrow
+///
+/// New variants go at the end of their range; existing discriminants never change.
 #[error_code]
-pub enum Lendingerror {
+pub enum LendingError {
+    // Core lending
     #[msg("Insufficient Funds available!!!")]
-    InsufficientFunds,
+    InsufficientFunds = 0,
     #[msg("Over Borrowable Amount!!!")]
-    OverBorrowableAmount,
+    OverBorrowableAmount = 1,
     #[msg("Over Repay Amount!!!")]
-    OverRepay,
+    OverRepay = 2,
     #[msg("Health Factor is above 1, liquidation not required!")]
-    HealthFactorAboveOne,
+    HealthFactorAboveOne = 3,
     #[msg("Math Overflow")]
-    MathOverflow,
+    MathOverflow = 4,
     #[msg("Insufficient Balance")]
-    InsufficientBalance,
-    #[msg("Oracle Price Error")] // Add this line
-    OracleError,
-}
\ No newline at end of file
+    InsufficientBalance = 5,
+    #[msg("Insufficient liquidity in the bank")]
+    InsufficientLiquidity = 6,
+    #[msg("Withdrawal would leave borrows above borrow power")]
+    WithdrawExceedsBorrowPower = 7,
+    #[msg("Mint or bank is not one of the user's two position assets")]
+    PositionMintMismatch = 8,
+
+    // Oracle
+    #[msg("Oracle Price Error")]
+    OracleError = 100,
+    #[msg("Cached oracle price is missing or too old")]
+    StalePrice = 101,
+    #[msg("Price update is not for this bank's feed")]
+    OracleFeedMismatch = 102,
+    #[msg("Bank has no oracle feed configured")]
+    OracleNotConfigured = 103,
+    #[msg("Primary and secondary oracle prices disagree")]
+    OracleDeviation = 104,
+    #[msg("Price circuit breaker is tripped; borrows and withdrawals are halted")]
+    CircuitBreakerTripped = 105,
+    #[msg("More price accounts than banks in the instruction")]
+    TooManyPriceAccounts = 106,
+    #[msg("Missing, mismatched or malformed stake pool account")]
+    InvalidStakePool = 107,
+    #[msg("Stake pool has not been updated this epoch")]
+    StakePoolNotUpdated = 108,
+    #[msg("Stake pool exchange rate is outside the bank's bounds")]
+    StakePoolRateOutOfBounds = 109,
+
+    // Admin, roles and bank configuration
+    #[msg("Unauthorized access")]
+    Unauthorized = 200,
+    #[msg("No admin transfer is pending")]
+    NoPendingAdminTransfer = 201,
+    #[msg("Amount exceeds protocol reserves")]
+    InsufficientReserves = 202,
+    #[msg("Guardian may only lower risk parameters")]
+    RiskNotReduced = 203,
+    #[msg("Liquidation threshold must be between 1 and 10000 bps")]
+    InvalidLiquidationThreshold = 204,
+    #[msg("Max LTV must be greater than zero")]
+    InvalidMaxLtv = 205,
+    #[msg("Max LTV must not exceed the liquidation threshold")]
+    MaxLtvAboveLiquidationThreshold = 206,
+    #[msg("Liquidation bonus exceeds the maximum")]
+    InvalidLiquidationBonus = 207,
+    #[msg("Liquidation close factor must be between 1 and 10000 bps")]
+    InvalidCloseFactor = 208,
+    #[msg("Interest rate parameter exceeds the maximum")]
+    InvalidInterestRate = 209,
+    #[msg("Kink utilization must be between 1 and 10000 bps")]
+    InvalidKinkUtilization = 210,
+    #[msg("Reserve factor must not exceed 10000 bps")]
+    InvalidReserveFactor = 211,
+    #[msg("Minimum liquidity buffer must not exceed 10000 bps")]
+    InvalidLiquidityBuffer = 212,
+    #[msg("Origination fee exceeds the maximum")]
+    InvalidOriginationFee = 213,
+    #[msg("Flash loan fee exceeds the maximum")]
+    InvalidFlashLoanFee = 214,
+    #[msg("Flash loan protocol share must not exceed 10000 bps")]
+    InvalidFlashLoanProtocolShare = 215,
+    #[msg("Oracle account supplied that the bank is not configured to use")]
+    InvalidOracleConfig = 216,
+    #[msg("Max price age must be greater than zero")]
+    InvalidMaxPriceAge = 217,
+    #[msg("Secondary Pyth feed selected without a feed id")]
+    SecondaryFeedNotSet = 218,
+    #[msg("Circuit breaker window and cooldown must not be negative")]
+    InvalidCircuitBreakerConfig = 219,
+    #[msg("Stake pool rate bounds must be positive with min not above max")]
+    InvalidStakePoolRateBounds = 220,
+    #[msg("No bank authority transfer is pending")]
+    NoPendingAuthorityTransfer = 221,
+
+    // Flash loans
+    #[msg("Flash loan is not active")]
+    FlashLoanNotActive = 300,
+    #[msg("Unauthorized to repay this flash loan")]
+    UnauthorizedFlashLoan = 301,
+    #[msg("Flash loan must be repaid within the same transaction")]
+    FlashLoanMustBeRepaidInSameTransaction = 302,
+    #[msg("Insufficient balance for repayment")]
+    InsufficientBalanceForRepayment = 303,
+    #[msg("Flash loans cannot be nested or taken more than once before repayment")]
+    NestedFlashLoan = 304,
+    #[msg("Flash loans must be initiated by a top-level instruction")]
+    FlashLoanCpiNotAllowed = 305,
+    #[msg("Repayment amount does not match the flash loan")]
+    FlashLoanAmountMismatch = 306,
+    #[msg("A flash loan is in progress on this bank")]
+    FlashLoanActive = 307,
+    #[msg("Flash loan receiver did not return the amount plus fee")]
+    FlashLoanNotRepaid = 308,
+    #[msg("Invalid flash loan receiver program")]
+    InvalidFlashLoanReceiver = 309,
+    #[msg("Flash loan assets do not match the accounts provided")]
+    InvalidFlashLoanAssets = 310,
+
+    // Governance
+    #[msg("Insufficient stake to propose or vote")]
+    InsufficientStake = 400,
+    #[msg("Voting period has ended")]
+    VotingEnded = 401,
+    #[msg("Voting period has not ended yet")]
+    VotingNotEnded = 402,
+    #[msg("Proposal was defeated by votes")]
+    ProposalDefeated = 403,
+    #[msg("Missing or malformed Ed25519 signature instruction")]
+    InvalidSignatureInstruction = 404,
+    #[msg("Signed vote message does not match this proposal")]
+    InvalidSignedVoteMessage = 405,
+    #[msg("Voter accounts do not match the signed votes")]
+    SignedVoteAccountsMismatch = 406,
+    #[msg("Voter has already voted on this proposal")]
+    AlreadyVoted = 407,
+    #[msg("Proposal bond has already been settled")]
+    BondAlreadySettled = 408,
+    #[msg("Missing user account or vote lock for voting power")]
+    VotingPowerAccountMissing = 409,
+    #[msg("Voting power account does not belong to the voter or governance mint")]
+    VotingPowerAccountMismatch = 410,
+    #[msg("Vote lock expires before the voting period ends")]
+    LockExpiresBeforeVotingEnds = 411,
+    #[msg("Proposal did not reach quorum")]
+    QuorumNotReached = 412,
+
+    // Vote escrow
+    #[msg("Lock amount must be greater than zero")]
+    InvalidLockAmount = 500,
+    #[msg("Lock duration must be positive and at most the maximum lock duration")]
+    InvalidLockDuration = 501,
+    #[msg("Lock has not expired yet")]
+    LockNotExpired = 502,
+}
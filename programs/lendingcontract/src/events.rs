@@ -0,0 +1,418 @@
+use anchor_lang::prelude::*;
+use crate::state::{PriceSource, SecondaryOracle};
+
+// Events are emitted through `emit_cpi!` so indexers can read them from inner
+// instructions instead of parsing logs. Risk parameters and rates are in basis points.
+
+// --- Lending ---
+#[event]
+pub struct Deposited {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_deposits: u64,
+    pub total_deposits_shares: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_deposits: u64,
+    pub total_deposits_shares: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    // Prices of this bank and the user's other bank; only read with open borrows, else 0 and None
+    pub price: i64,
+    pub price_exponent: i32,
+    pub price_source: Option<PriceSource>,
+    pub other_price: i64,
+    pub other_price_exponent: i32,
+    pub other_price_source: Option<PriceSource>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Borrowed {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    // Added to the debt on top of `amount` and credited to protocol reserves as it is repaid
+    pub origination_fee: u64,
+    pub shares: u64,
+    pub total_borrowed: u64,
+    pub total_borrowed_shares: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    pub price: i64,
+    pub price_exponent: i32,
+    pub price_source: PriceSource,
+    pub collateral_price: i64,
+    pub collateral_price_exponent: i32,
+    pub collateral_price_source: PriceSource,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Repaid {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_borrowed: u64,
+    pub total_borrowed_shares: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Liquidated {
+    pub liquidator: Pubkey,
+    pub user: Pubkey,
+    pub collateral_bank: Pubkey,
+    pub debt_bank: Pubkey,
+    pub debt_repaid: u64,
+    pub debt_shares_burned: u64,
+    pub collateral_seized: u64,
+    pub collateral_shares_burned: u64,
+    pub collateral_price: i64,
+    pub collateral_price_exponent: i32,
+    pub debt_price: i64,
+    pub debt_price_exponent: i32,
+    pub collateral_price_source: PriceSource,
+    pub debt_price_source: PriceSource,
+    // Health factor before liquidation, scaled by 1e18
+    pub health_factor: u128,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PriceUpdated {
+    pub bank: Pubkey,
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub twap_price: i64,
+    // Spot moved further from the TWAP than the bank's max_twap_deviation
+    pub jump_flagged: bool,
+    // Zero when no secondary feed was supplied or it was stale
+    pub secondary_price: i64,
+    pub secondary_publish_time: i64,
+    // SOL-per-LST rate in basis points the price was derived with; zero outside LST mode
+    pub stake_pool_rate: u64,
+}
+
+#[event]
+pub struct InterestAccrued {
+    pub bank: Pubkey,
+    pub total_deposits: u64,
+    pub total_borrowed: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    pub timestamp: i64,
+}
+
+// --- Flash loans ---
+#[event]
+pub struct FlashLoanInitiated {
+    pub borrower: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FlashLoanRepaid {
+    pub borrower: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FlashLoanExecuted {
+    pub borrower: Pubkey,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub receiver_program: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+// --- Admin ---
+#[event]
+pub struct BankInitialized {
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub authority: Pubkey,
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+    pub max_ltv: u64,
+    pub base_rate: u64,
+    pub multiplier: u64,
+    pub jump_multiplier: u64,
+    pub kink_utilization: u64,
+    pub reserve_factor: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserInitialized {
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub usdc_address: Pubkey,
+    pub sol_address: Pubkey,
+}
+
+#[event]
+pub struct RolesUpdated {
+    pub updated_by: Pubkey,
+    pub admin: Pubkey,
+    pub risk_admin: Pubkey,
+    pub rate_admin: Pubkey,
+    pub guardian: Pubkey,
+    pub fee_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferCancelled {
+    pub cancelled_admin: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub bank: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferCancelled {
+    pub bank: Pubkey,
+    pub cancelled_authority: Pubkey,
+}
+
+#[event]
+pub struct BankConfigUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+    pub max_ltv: u64,
+    pub min_liquidity_buffer: u64,
+    pub origination_fee: u64,
+}
+
+#[event]
+pub struct InterestParamsUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub base_rate: u64,
+    pub multiplier: u64,
+    pub jump_multiplier: u64,
+    pub kink_utilization: u64,
+    pub reserve_factor: u64,
+}
+
+#[event]
+pub struct FlashLoanFeeUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub flash_loan_fee: u64,
+    pub flash_loan_protocol_share: u64,
+}
+
+#[event]
+pub struct FeeExemptionUpdated {
+    pub keeper: Pubkey,
+    pub fee_admin: Pubkey,
+    pub exempt: bool,
+}
+
+#[event]
+pub struct OracleConfigUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub oracle_feed_id: [u8; 32],
+    pub max_price_age: u64,
+    pub max_twap_deviation: u64,
+    pub secondary_oracle: SecondaryOracle,
+    pub secondary_feed_id: [u8; 32],
+    pub max_oracle_deviation: u64,
+}
+
+#[event]
+pub struct GovernanceMintUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub governance_mint: Pubkey,
+}
+
+#[event]
+pub struct GovernanceParamsUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub proposal_bond: u64,
+    pub min_proposer_power: u64,
+    pub quorum_votes: u64,
+}
+
+#[event]
+pub struct EmergencyPaused {
+    pub bank: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OperationsResumed {
+    pub bank: Pubkey,
+    pub risk_admin: Pubkey,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReservesWithdrawn {
+    pub bank: Pubkey,
+    pub fee_admin: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub protocol_reserves: u64,
+}
+
+// --- Governance ---
+#[event]
+pub struct ProposalCreated {
+    pub proposal: Pubkey,
+    pub bank: Pubkey,
+    pub proposer: Pubkey,
+    pub id: u64,
+    pub proposal_type: u8,
+    pub params: [u64; 5],
+    pub bond_amount: u64,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct VoteCast {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub vote_for: bool,
+    pub voting_power: u64,
+    // True when relayed through submit_signed_votes
+    pub signed: bool,
+    pub votes_for: u64,
+    pub votes_against: u64,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub proposal: Pubkey,
+    pub bank: Pubkey,
+    pub executor: Pubkey,
+    pub proposal_type: u8,
+    pub votes_for: u64,
+    pub votes_against: u64,
+}
+
+#[event]
+pub struct ProposalBondSettled {
+    pub proposal: Pubkey,
+    pub bank: Pubkey,
+    pub proposer: Pubkey,
+    pub bond_amount: u64,
+    pub reached_quorum: bool,
+    pub protocol_reserves: u64,
+}
+
+#[event]
+pub struct GovernanceTokensLocked {
+    pub vote_lock: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub lock_end: i64,
+}
+
+#[event]
+pub struct GovernanceTokensUnlocked {
+    pub vote_lock: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CircuitBreakerTripped {
+    pub bank: Pubkey,
+    // Price at the start of the window the move is measured from
+    pub reference_price: i64,
+    pub price: i64,
+    pub exponent: i32,
+    pub tripped_at: i64,
+}
+
+#[event]
+pub struct CircuitBreakerReset {
+    pub bank: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CircuitBreakerConfigUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub threshold: u64,
+    pub window: i64,
+    pub cooldown: i64,
+}
+
+#[event]
+pub struct StakePoolOracleUpdated {
+    pub bank: Pubkey,
+    pub updated_by: Pubkey,
+    pub stake_pool: Pubkey,
+    pub min_stake_pool_rate: u64,
+    pub max_stake_pool_rate: u64,
+}
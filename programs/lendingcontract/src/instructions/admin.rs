@@ -1,11 +1,17 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{ Mint, TokenAccount, TokenInterface };
-use crate::state::*;
+use anchor_spl::token_interface::{ self, Mint, TokenAccount, TokenInterface, TransferChecked };
+use crate::{constants::MAX_AGE, error::LendingError, events::*, program::Lending, state::*};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct InitializeBank<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
     pub mint: InterfaceAccount<'info, Mint>,
     #[account(
         init,
@@ -28,6 +34,7 @@ pub struct InitializeBank<'info> {
     pub system_program: Program<'info, System>
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct InitializeUser<'info>{
     #[account(mut)]
@@ -43,13 +50,53 @@ pub struct InitializeUser<'info>{
     pub system_program: Program<'info, System>
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeRoles<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + LendingRoles::INIT_SPACE,
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+    // Only the program's upgrade authority may set up the market roles
+    #[account(constraint = lending_program.programdata_address()? == Some(program_data.key()))]
+    pub lending_program: Program<'info, Lending>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(signer.key()) @ LendingError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateRoles<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ LendingError::Unauthorized,
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+}
+
+// Role checks happen in each handler, since several admin instructions share these accounts
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdateBankParams<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
     #[account(
         mut,
-        has_one = authority,
         seeds = [mint.key().as_ref()],
         bump,
     )]
@@ -57,24 +104,154 @@ pub struct UpdateBankParams<'info> {
     pub mint: InterfaceAccount<'info, Mint>,
 }
 
-// New Struct for Transferring Authority
+#[event_cpi]
 #[derive(Accounts)]
-pub struct TransferAuthority<'info> {
+pub struct WithdrawReserves<'info> {
+    pub fee_admin: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+        constraint = roles.fee_admin == fee_admin.key() @ LendingError::Unauthorized,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(keeper: Pubkey)]
+pub struct AddFeeExemption<'info> {
+    #[account(mut)]
+    pub fee_admin: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+        constraint = roles.fee_admin == fee_admin.key() @ LendingError::Unauthorized,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+    #[account(
+        init,
+        payer = fee_admin,
+        space = 8 + FeeExemption::INIT_SPACE,
+        seeds = [b"fee_exempt", keeper.as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveFeeExemption<'info> {
     #[account(mut)]
+    pub fee_admin: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+        constraint = roles.fee_admin == fee_admin.key() @ LendingError::Unauthorized,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+    #[account(
+        mut,
+        seeds = [b"fee_exempt", fee_exemption.keeper.as_ref()],
+        bump,
+        close = fee_admin,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct TransferAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ LendingError::Unauthorized,
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+    /// CHECK: This is the proposed admin/DAO address; it must accept before taking over
+    pub new_admin: AccountInfo<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(
+        mut,
+        has_one = pending_admin @ LendingError::Unauthorized,
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
     pub current_authority: Signer<'info>,
     #[account(
         mut,
-        // FIX: Use constraint because 'bank.authority' does not match 'current_authority' name
         constraint = bank.authority == current_authority.key() @ LendingError::Unauthorized,
         seeds = [mint.key().as_ref()],
         bump,
     )]
     pub bank: Account<'info, Bank>,
     pub mint: InterfaceAccount<'info, Mint>,
-    /// CHECK: This is the new admin/DAO address
-    pub new_authority: AccountInfo<'info>, 
+    /// CHECK: This is the proposed bank authority; it must accept before taking over
+    pub new_authority: AccountInfo<'info>,
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelAuthorityTransfer<'info> {
+    pub current_authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = bank.authority == current_authority.key() @ LendingError::Unauthorized,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub pending_authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = bank.pending_authority == pending_authority.key() @ LendingError::Unauthorized,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn initbank(
     ctx: Context<InitializeBank>,
     liquidation_threshold: u64,
@@ -85,43 +262,228 @@ pub fn initbank(
     kink_utilization: Option<u64>,
     reserve_factor: Option<u64>,
 ) -> Result<()> {
+    // The bank PDA can only be created once per mint, so listing is reserved to the admin or risk admin
+    let signer = ctx.accounts.signer.key();
+    let roles = &ctx.accounts.roles;
+    require!(signer == roles.admin || signer == roles.risk_admin, LendingError::Unauthorized);
     let bank = &mut ctx.accounts.bank;
+    bank.authority = signer;
+    bank.pending_authority = Pubkey::default();
     bank.mint_address = ctx.accounts.mint.key();
-    bank.authority = ctx.accounts.signer.key();
-    bank.liquidation_threshold = liquidation_threshold;
-    bank.max_ltv = max_ltv;
+    bank.mint_decimals = ctx.accounts.mint.decimals;
+    bank.liquidation_threshold = Bps(liquidation_threshold);
+    bank.max_ltv = Bps(max_ltv);
     
     bank.total_deposits = 0;
     bank.total_deposits_shares = 0;
     bank.total_borrowed = 0;
     bank.total_borrowed_shares = 0;
-    bank.liquidation_bonus = 500;
-    bank.liquidation_close_factor = 5000;
+    bank.liquidation_bonus = Bps(500);
+    bank.liquidation_close_factor = Bps(5000);
 
     bank.base_rate = base_rate.unwrap_or(200);
     bank.multiplier = multiplier.unwrap_or(500);
     bank.jump_multiplier = jump_multiplier.unwrap_or(5000);
-    bank.kink_utilization = kink_utilization.unwrap_or(8000);
-    bank.reserve_factor = reserve_factor.unwrap_or(1000);
+    bank.kink_utilization = Bps(kink_utilization.unwrap_or(8000));
+    bank.reserve_factor = Bps(reserve_factor.unwrap_or(1000));
     
+    bank.protocol_reserves = 0;
+    bank.proposal_bond = 0;
+    bank.min_proposer_power = 1;
+    bank.quorum_votes = 0;
+    bank.governance_mint = Pubkey::default();
+    bank.min_liquidity_buffer = Bps::ZERO;
+    bank.origination_fee = Bps::ZERO;
+    bank.uncollected_origination_fees = 0;
+    bank.flash_loan_active = false;
+    bank.flash_loan_fee = Bps(9);
+    bank.flash_loan_protocol_share = Bps::ZERO;
+    // No feed until the risk admin sets one with update_oracle_config
+    bank.oracle_feed_id = [0; 32];
+    bank.max_price_age = MAX_AGE;
+    bank.max_twap_deviation = Bps(1000);
+    bank.secondary_oracle = SecondaryOracle::None;
+    bank.secondary_feed_id = [0; 32];
+    bank.max_oracle_deviation = Bps(500);
+    bank.circuit_breaker_threshold = Bps(2000);
+    bank.circuit_breaker_window = 5 * 60;
+    bank.circuit_breaker_cooldown = 30 * 60;
+    bank.circuit_breaker_reference_price = 0;
+    bank.circuit_breaker_reference_time = 0;
+    bank.circuit_breaker_tripped_at = 0;
+    bank.stake_pool = Pubkey::default();
+    bank.min_stake_pool_rate = Bps::ONE;
+    bank.max_stake_pool_rate = Bps(20000);
+
+    bank.validate_config()?;
+
     bank.current_borrow_rate = bank.base_rate;
     bank.current_supply_rate = 0;
     bank.last_updated = Clock::get()?.unix_timestamp;
+
+    emit_cpi!(BankInitialized {
+        bank: bank.key(),
+        mint: bank.mint_address,
+        authority: bank.authority,
+        liquidation_threshold: bank.liquidation_threshold.0,
+        liquidation_bonus: bank.liquidation_bonus.0,
+        liquidation_close_factor: bank.liquidation_close_factor.0,
+        max_ltv: bank.max_ltv.0,
+        base_rate: bank.base_rate,
+        multiplier: bank.multiplier,
+        jump_multiplier: bank.jump_multiplier,
+        kink_utilization: bank.kink_utilization.0,
+        reserve_factor: bank.reserve_factor.0,
+        timestamp: bank.last_updated,
+    });
     
     Ok(())
 }
 
-pub fn inituser(ctx: Context<InitializeUser>, usdc_address: Pubkey) -> Result<()> {
+pub fn inituser(ctx: Context<InitializeUser>, usdc_address: Pubkey, sol_address: Pubkey) -> Result<()> {
+    require_keys_neq!(usdc_address, sol_address, LendingError::PositionMintMismatch);
     let user_account = &mut ctx.accounts.user_account;
     user_account.owner = ctx.accounts.signer.key();
     user_account.usdc_address = usdc_address;
+    user_account.sol_address = sol_address;
+
+    emit_cpi!(UserInitialized {
+        user: user_account.key(),
+        owner: user_account.owner,
+        usdc_address,
+        sol_address,
+    });
+    Ok(())
+}
+
+pub fn transfer_admin(ctx: Context<TransferAdmin>) -> Result<()> {
+    let roles = &mut ctx.accounts.roles;
+    roles.pending_admin = ctx.accounts.new_admin.key();
+    msg!("Admin transfer proposed to: {}", roles.pending_admin);
+
+    emit_cpi!(AdminTransferProposed {
+        admin: roles.admin,
+        pending_admin: roles.pending_admin,
+    });
+    Ok(())
+}
+
+pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+    let roles = &mut ctx.accounts.roles;
+    let previous_admin = roles.admin;
+    roles.admin = ctx.accounts.pending_admin.key();
+    roles.pending_admin = Pubkey::default();
+    msg!("Admin transferred to: {}", roles.admin);
+
+    emit_cpi!(AdminTransferred {
+        previous_admin,
+        new_admin: roles.admin,
+    });
+    Ok(())
+}
+
+pub fn initialize_roles(ctx: Context<InitializeRoles>) -> Result<()> {
+    let roles = &mut ctx.accounts.roles;
+    let admin = ctx.accounts.signer.key();
+    roles.admin = admin;
+    roles.pending_admin = Pubkey::default();
+    roles.risk_admin = admin;
+    roles.rate_admin = admin;
+    roles.guardian = admin;
+    roles.fee_admin = admin;
+    msg!("Lending roles initialized with admin: {}", admin);
+
+    emit_cpi!(RolesUpdated {
+        updated_by: admin,
+        admin: roles.admin,
+        risk_admin: roles.risk_admin,
+        rate_admin: roles.rate_admin,
+        guardian: roles.guardian,
+        fee_admin: roles.fee_admin,
+    });
+    Ok(())
+}
+
+// The admin itself changes only through the two-step transfer_admin/accept_admin
+pub fn update_roles(
+    ctx: Context<UpdateRoles>,
+    risk_admin: Option<Pubkey>,
+    rate_admin: Option<Pubkey>,
+    guardian: Option<Pubkey>,
+    fee_admin: Option<Pubkey>,
+) -> Result<()> {
+    let roles = &mut ctx.accounts.roles;
+
+    if let Some(key) = risk_admin { roles.risk_admin = key; }
+    if let Some(key) = rate_admin { roles.rate_admin = key; }
+    if let Some(key) = guardian { roles.guardian = key; }
+    if let Some(key) = fee_admin { roles.fee_admin = key; }
+
+    msg!("Lending roles updated by admin: {}", ctx.accounts.admin.key());
+
+    emit_cpi!(RolesUpdated {
+        updated_by: ctx.accounts.admin.key(),
+        admin: roles.admin,
+        risk_admin: roles.risk_admin,
+        rate_admin: roles.rate_admin,
+        guardian: roles.guardian,
+        fee_admin: roles.fee_admin,
+    });
     Ok(())
 }
 
 pub fn transfer_authority(ctx: Context<TransferAuthority>) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
-    bank.authority = ctx.accounts.new_authority.key();
+    bank.pending_authority = ctx.accounts.new_authority.key();
+    msg!("Bank authority transfer proposed to: {}", bank.pending_authority);
+
+    emit_cpi!(AuthorityTransferProposed {
+        bank: bank.key(),
+        authority: bank.authority,
+        pending_authority: bank.pending_authority,
+    });
+    Ok(())
+}
+
+pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    let previous_authority = bank.authority;
+    bank.authority = ctx.accounts.pending_authority.key();
+    bank.pending_authority = Pubkey::default();
     msg!("Bank authority transferred to: {}", bank.authority);
+
+    emit_cpi!(AuthorityTransferred {
+        bank: bank.key(),
+        previous_authority,
+        new_authority: bank.authority,
+    });
+    Ok(())
+}
+
+pub fn cancel_authority_transfer(ctx: Context<CancelAuthorityTransfer>) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    require!(bank.pending_authority != Pubkey::default(), LendingError::NoPendingAuthorityTransfer);
+    let cancelled_authority = bank.pending_authority;
+    bank.pending_authority = Pubkey::default();
+    msg!("Bank authority transfer cancelled");
+
+    emit_cpi!(AuthorityTransferCancelled {
+        bank: bank.key(),
+        cancelled_authority,
+    });
+    Ok(())
+}
+
+pub fn cancel_admin_transfer(ctx: Context<UpdateRoles>) -> Result<()> {
+    let roles = &mut ctx.accounts.roles;
+    require!(roles.pending_admin != Pubkey::default(), LendingError::NoPendingAdminTransfer);
+    let cancelled_admin = roles.pending_admin;
+    roles.pending_admin = Pubkey::default();
+    msg!("Admin transfer cancelled");
+
+    emit_cpi!(AdminTransferCancelled {
+        cancelled_admin,
+    });
     Ok(())
 }
 
@@ -133,16 +495,28 @@ pub fn update_bank_kink_params(
     kink_utilization: Option<u64>,
     reserve_factor: Option<u64>,
 ) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.rate_admin, LendingError::Unauthorized);
     let bank = &mut ctx.accounts.bank;
     bank.update_interest()?;
     
     if let Some(rate) = base_rate { bank.base_rate = rate; }
     if let Some(mult) = multiplier { bank.multiplier = mult; }
     if let Some(jump) = jump_multiplier { bank.jump_multiplier = jump; }
-    if let Some(kink) = kink_utilization { bank.kink_utilization = kink; }
-    if let Some(reserve) = reserve_factor { bank.reserve_factor = reserve; }
+    if let Some(kink) = kink_utilization { bank.kink_utilization = Bps(kink); }
+    if let Some(reserve) = reserve_factor { bank.reserve_factor = Bps(reserve); }
+    bank.validate_config()?;
     
     msg!("Bank parameters updated by authority: {}", ctx.accounts.authority.key());
+
+    emit_cpi!(InterestParamsUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        base_rate: bank.base_rate,
+        multiplier: bank.multiplier,
+        jump_multiplier: bank.jump_multiplier,
+        kink_utilization: bank.kink_utilization.0,
+        reserve_factor: bank.reserve_factor.0,
+    });
     Ok(())
 }
 
@@ -152,25 +526,252 @@ pub fn update_bank_config(
     liquidation_bonus: Option<u64>,
     liquidation_close_factor: Option<u64>,
     max_ltv: Option<u64>,
+    min_liquidity_buffer: Option<u64>,
+    origination_fee: Option<u64>,
 ) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.risk_admin, LendingError::Unauthorized);
     let bank = &mut ctx.accounts.bank;
     
-    if let Some(threshold) = liquidation_threshold { bank.liquidation_threshold = threshold; }
-    if let Some(bonus) = liquidation_bonus { bank.liquidation_bonus = bonus; }
-    if let Some(factor) = liquidation_close_factor { bank.liquidation_close_factor = factor; }
-    if let Some(ltv) = max_ltv { bank.max_ltv = ltv; }
+    if let Some(threshold) = liquidation_threshold { bank.liquidation_threshold = Bps(threshold); }
+    if let Some(bonus) = liquidation_bonus { bank.liquidation_bonus = Bps(bonus); }
+    if let Some(factor) = liquidation_close_factor { bank.liquidation_close_factor = Bps(factor); }
+    if let Some(ltv) = max_ltv { bank.max_ltv = Bps(ltv); }
+    if let Some(buffer) = min_liquidity_buffer { bank.min_liquidity_buffer = Bps(buffer); }
+    if let Some(fee) = origination_fee { bank.origination_fee = Bps(fee); }
+    bank.validate_config()?;
     
     msg!("Bank configuration updated by authority: {}", ctx.accounts.authority.key());
+
+    emit_cpi!(BankConfigUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        liquidation_threshold: bank.liquidation_threshold.0,
+        liquidation_bonus: bank.liquidation_bonus.0,
+        liquidation_close_factor: bank.liquidation_close_factor.0,
+        max_ltv: bank.max_ltv.0,
+        min_liquidity_buffer: bank.min_liquidity_buffer.0,
+        origination_fee: bank.origination_fee.0,
+    });
+    Ok(())
+}
+
+pub fn update_oracle_config(
+    ctx: Context<UpdateBankParams>,
+    oracle_feed_id: Option<[u8; 32]>,
+    max_price_age: Option<u64>,
+    max_twap_deviation: Option<u64>,
+    secondary_oracle: Option<SecondaryOracle>,
+    secondary_feed_id: Option<[u8; 32]>,
+    max_oracle_deviation: Option<u64>,
+) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.risk_admin, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    
+    if let Some(feed_id) = oracle_feed_id {
+        // A new feed invalidates the cache, TWAP and breaker reference built from the old one
+        bank.oracle_feed_id = feed_id;
+        bank.price = 0;
+        bank.price_publish_time = 0;
+        bank.twap_price = 0;
+        bank.price_jump_flagged = false;
+        bank.circuit_breaker_reference_price = 0;
+    }
+    if let Some(age) = max_price_age { bank.max_price_age = age; }
+    if let Some(deviation) = max_twap_deviation { bank.max_twap_deviation = Bps(deviation); }
+    if let Some(source) = secondary_oracle { bank.secondary_oracle = source; }
+    if let Some(feed_id) = secondary_feed_id {
+        bank.secondary_feed_id = feed_id;
+        bank.secondary_price = 0;
+        bank.secondary_price_publish_time = 0;
+    }
+    if let Some(deviation) = max_oracle_deviation { bank.max_oracle_deviation = Bps(deviation); }
+    bank.validate_config()?;
+    
+    msg!("Oracle config updated by authority: {}", ctx.accounts.authority.key());
+
+    emit_cpi!(OracleConfigUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        oracle_feed_id: bank.oracle_feed_id,
+        max_price_age: bank.max_price_age,
+        max_twap_deviation: bank.max_twap_deviation.0,
+        secondary_oracle: bank.secondary_oracle,
+        secondary_feed_id: bank.secondary_feed_id,
+        max_oracle_deviation: bank.max_oracle_deviation.0,
+    });
+    Ok(())
+}
+
+pub fn set_stake_pool_oracle(
+    ctx: Context<UpdateBankParams>,
+    stake_pool: Pubkey,
+    min_stake_pool_rate: u64,
+    max_stake_pool_rate: u64,
+) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.risk_admin, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    
+    // Pubkey::default() switches back to pricing straight from the Pyth feed. Either way the
+    // cached price changes meaning, so the cache, TWAP and breaker reference start over.
+    bank.stake_pool = stake_pool;
+    bank.min_stake_pool_rate = Bps(min_stake_pool_rate);
+    bank.max_stake_pool_rate = Bps(max_stake_pool_rate);
+    bank.price = 0;
+    bank.price_publish_time = 0;
+    bank.twap_price = 0;
+    bank.price_jump_flagged = false;
+    bank.circuit_breaker_reference_price = 0;
+    bank.validate_config()?;
+    
+    msg!("Stake pool oracle set to: {}", stake_pool);
+
+    emit_cpi!(StakePoolOracleUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        stake_pool,
+        min_stake_pool_rate,
+        max_stake_pool_rate,
+    });
+    Ok(())
+}
+
+pub fn update_circuit_breaker(
+    ctx: Context<UpdateBankParams>,
+    threshold: Option<u64>,
+    window: Option<i64>,
+    cooldown: Option<i64>,
+) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.risk_admin, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    
+    if let Some(threshold) = threshold { bank.circuit_breaker_threshold = Bps(threshold); }
+    if let Some(window) = window { bank.circuit_breaker_window = window; }
+    if let Some(cooldown) = cooldown { bank.circuit_breaker_cooldown = cooldown; }
+    bank.validate_config()?;
+    
+    msg!("Circuit breaker updated by authority: {}", ctx.accounts.authority.key());
+
+    emit_cpi!(CircuitBreakerConfigUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        threshold: bank.circuit_breaker_threshold.0,
+        window: bank.circuit_breaker_window,
+        cooldown: bank.circuit_breaker_cooldown,
+    });
+    Ok(())
+}
+
+pub fn update_flash_loan_fee(
+    ctx: Context<UpdateBankParams>,
+    flash_loan_fee: Option<u64>,
+    flash_loan_protocol_share: Option<u64>,
+) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.fee_admin, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    
+    if let Some(fee) = flash_loan_fee { bank.flash_loan_fee = Bps(fee); }
+    if let Some(share) = flash_loan_protocol_share { bank.flash_loan_protocol_share = Bps(share); }
+    bank.validate_config()?;
+    
+    msg!("Flash loan fee set to {} bps, {} bps of it to reserves", bank.flash_loan_fee.0, bank.flash_loan_protocol_share.0);
+
+    emit_cpi!(FlashLoanFeeUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        flash_loan_fee: bank.flash_loan_fee.0,
+        flash_loan_protocol_share: bank.flash_loan_protocol_share.0,
+    });
+    Ok(())
+}
+
+pub fn add_fee_exemption(ctx: Context<AddFeeExemption>, keeper: Pubkey) -> Result<()> {
+    ctx.accounts.fee_exemption.keeper = keeper;
+    msg!("Flash loan fee exemption added for {}", keeper);
+
+    emit_cpi!(FeeExemptionUpdated {
+        keeper,
+        fee_admin: ctx.accounts.fee_admin.key(),
+        exempt: true,
+    });
+    Ok(())
+}
+
+pub fn remove_fee_exemption(ctx: Context<RemoveFeeExemption>) -> Result<()> {
+    let keeper = ctx.accounts.fee_exemption.keeper;
+    msg!("Flash loan fee exemption removed for {}", keeper);
+
+    emit_cpi!(FeeExemptionUpdated {
+        keeper,
+        fee_admin: ctx.accounts.fee_admin.key(),
+        exempt: false,
+    });
+    Ok(())
+}
+
+pub fn set_governance_mint(ctx: Context<UpdateBankParams>, governance_mint: Pubkey) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.admin, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    // Pubkey::default() switches voting power back to deposit shares
+    bank.governance_mint = governance_mint;
+    msg!("Governance mint set to: {}", governance_mint);
+
+    emit_cpi!(GovernanceMintUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        governance_mint,
+    });
+    Ok(())
+}
+
+pub fn withdraw_reserves(ctx: Context<WithdrawReserves>, amount: u64) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    bank.require_no_active_flash_loan()?;
+    require!(amount <= bank.protocol_reserves, LendingError::InsufficientReserves);
+
+    let transfer_cpi_acc = TransferChecked {
+        from: ctx.accounts.bank_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        to: ctx.accounts.destination.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
+    };
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[
+        &[
+            b"treasury",
+            mint_key.as_ref(),
+            &[ctx.bumps.bank_token_account],
+        ]
+    ];
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_cpi_acc).with_signer(signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+    bank.protocol_reserves -= amount;
+    msg!("Withdrew {} from protocol reserves", amount);
+
+    emit_cpi!(ReservesWithdrawn {
+        bank: bank.key(),
+        fee_admin: ctx.accounts.fee_admin.key(),
+        destination: ctx.accounts.destination.key(),
+        amount,
+        protocol_reserves: bank.protocol_reserves,
+    });
     Ok(())
 }
 
+// The guardian may pause or reduce risk; anything that re-opens risk needs the risk admin,
+// except resetting a tripped price circuit breaker
+#[event_cpi]
 #[derive(Accounts)]
 pub struct EmergencyControl<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"roles"],
+        bump,
+    )]
+    pub roles: Account<'info, LendingRoles>,
     #[account(
         mut,
-        has_one = authority,
         seeds = [mint.key().as_ref()],
         bump,
     )]
@@ -179,25 +780,74 @@ pub struct EmergencyControl<'info> {
 }
 
 pub fn emergency_pause(ctx: Context<EmergencyControl>) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.guardian, LendingError::Unauthorized);
     let bank = &mut ctx.accounts.bank;
     bank.update_interest()?;
     bank.current_borrow_rate = 0;
     bank.current_supply_rate = 0;
     msg!("Emergency pause activated.");
+
+    emit_cpi!(EmergencyPaused {
+        bank: bank.key(),
+        guardian: ctx.accounts.authority.key(),
+        timestamp: bank.last_updated,
+    });
+    Ok(())
+}
+
+pub fn reduce_max_ltv(ctx: Context<EmergencyControl>, max_ltv: u64) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.guardian, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    require!(Bps(max_ltv) < bank.max_ltv, LendingError::RiskNotReduced);
+    bank.max_ltv = Bps(max_ltv);
+    bank.validate_config()?;
+    msg!("Max LTV reduced to {} by guardian", max_ltv);
+
+    emit_cpi!(BankConfigUpdated {
+        bank: bank.key(),
+        updated_by: ctx.accounts.authority.key(),
+        liquidation_threshold: bank.liquidation_threshold.0,
+        liquidation_bonus: bank.liquidation_bonus.0,
+        liquidation_close_factor: bank.liquidation_close_factor.0,
+        max_ltv: bank.max_ltv.0,
+        min_liquidity_buffer: bank.min_liquidity_buffer.0,
+        origination_fee: bank.origination_fee.0,
+    });
     Ok(())
 }
 
 pub fn resume_operations(ctx: Context<EmergencyControl>) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.risk_admin, LendingError::Unauthorized);
     let bank = &mut ctx.accounts.bank;
     bank.current_borrow_rate = bank.calculate_borrow_rate();
     bank.current_supply_rate = bank.calculate_supply_rate();
     bank.last_updated = Clock::get()?.unix_timestamp;
     msg!("Operations resumed.");
+
+    emit_cpi!(OperationsResumed {
+        bank: bank.key(),
+        risk_admin: ctx.accounts.authority.key(),
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        timestamp: bank.last_updated,
+    });
     Ok(())
 }
 
-#[error_code]
-pub enum LendingError {
-    #[msg("Unauthorized access")]
-    Unauthorized,
-}
\ No newline at end of file
+// Re-opens borrows and withdrawals before the cooldown once the guardian has checked the price
+pub fn reset_circuit_breaker(ctx: Context<EmergencyControl>) -> Result<()> {
+    require_keys_eq!(ctx.accounts.authority.key(), ctx.accounts.roles.guardian, LendingError::Unauthorized);
+    let bank = &mut ctx.accounts.bank;
+    bank.circuit_breaker_tripped_at = 0;
+    // Measure the next window from the current price, or the next update would trip it again
+    bank.circuit_breaker_reference_price = bank.price;
+    bank.circuit_breaker_reference_time = bank.price_publish_time;
+    msg!("Circuit breaker reset by guardian");
+
+    emit_cpi!(CircuitBreakerReset {
+        bank: bank.key(),
+        guardian: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
@@ -1,9 +1,9 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked}};
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 
-use crate::{constants::{MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID}, error::Lendingerror, state::{calculate_accrued_interest, Bank, User}};
+use crate::{error::LendingError, events::Borrowed, instructions::refresh_prices, state::{amount_to_shares, position_value, token_value, Bank, Rounding, User}};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Borrow<'info> {
     #[account(mut)]
@@ -38,44 +38,58 @@ pub struct Borrow<'info> {
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
-    pub price_update: Account<'info, PriceUpdateV2>,
+    // Bank of the user's other asset; together with `bank` it values the whole position
+    #[account(
+        mut,
+        seeds=[collateral_bank.mint_address.as_ref()],
+        bump,
+        constraint = collateral_bank.key() != bank.key(),
+        constraint = user_account.other_mint(mint.key()) == Some(collateral_bank.mint_address) @ LendingError::PositionMintMismatch,
+    )]
+    pub collateral_bank: Account<'info, Bank>,
 }
 
+// remaining_accounts: optional PriceUpdateV2 for [bank, collateral_bank], each on that bank's feed
 pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
-    let price_update = &mut ctx.accounts.price_update;
+    let collateral_bank = &mut ctx.accounts.collateral_bank;
+    
+    bank.require_no_active_flash_loan()?;
+    // A price passed in here that trips a breaker fails the borrow and is rolled back with
+    // it; update_price is the crank that records the trip
+    refresh_prices(ctx.remaining_accounts, &mut [bank, collateral_bank])?;
+    let now = Clock::get()?.unix_timestamp;
+    bank.require_circuit_breaker_closed(now)?;
+    // The collateral is valued at its bank's price too, which must not be moving abnormally
+    collateral_bank.require_circuit_breaker_closed(now)?;
     
     // Update interest rates before borrowing
     bank.update_interest()?;
     
-    let total_collateral: u64;
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)
-                .map_err(|_| Lendingerror::OracleError)?;
-                
-            let sol_price = price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)
-                .map_err(|_| Lendingerror::OracleError)?;
-                
-            let new_value = calculate_accrued_interest(user.deposited_usdc, bank.current_supply_rate, user.last_updated)?;
-            total_collateral = (sol_price.price as u64) * new_value;
-        }
-        _ => {
-            let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)
-                .map_err(|_| Lendingerror::OracleError)?;
-                
-            let usdc_price = price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)
-                .map_err(|_| Lendingerror::OracleError)?;
-                
-            let new_value = calculate_accrued_interest(user.deposited_sol, bank.current_supply_rate, user.last_updated)?;
-            total_collateral = new_value * (usdc_price.price as u64);
-        }
-    }
+    // Fail clearly up front instead of on the token transfer, and keep the withdrawal buffer intact
+    let borrowable_liquidity = bank.borrowable_liquidity(ctx.accounts.bank_token_account.amount)
+        .ok_or(LendingError::MathOverflow)?;
+    require!(amount <= borrowable_liquidity, LendingError::InsufficientLiquidity);
+    
+    let bank_price = bank.oracle_price(now)?;
+    let collateral_price = collateral_bank.oracle_price(now)?;
+    let position = if bank.mint_address == user.usdc_address {
+        position_value(user, bank, bank_price, collateral_bank, collateral_price)?
+    } else {
+        position_value(user, collateral_bank, collateral_price, bank, bank_price)?
+    };
 
-    let borrowable_amt = total_collateral.checked_mul(bank.liquidation_threshold).unwrap();
-    if borrowable_amt < amount {
-        return Err(Lendingerror::OverBorrowableAmount.into());
+    // The origination fee is owed on top of the borrowed amount, so it counts against borrow power
+    let origination_fee = bank.origination_fee.apply(amount).ok_or(LendingError::MathOverflow)?;
+    let debt = amount.checked_add(origination_fee).ok_or(LendingError::MathOverflow)?;
+    let total_debt_value = position.debt
+        .checked_add(token_value(debt, bank.mint_decimals, bank_price)?)
+        .ok_or(LendingError::MathOverflow)?;
+
+    // Borrowing is capped by each deposit's own max LTV; the liquidation threshold only governs liquidation
+    if position.borrow_power < total_debt_value {
+        return Err(LendingError::OverBorrowableAmount.into());
     }
 
     let transfer_cpi_acc = TransferChecked {
@@ -98,28 +112,60 @@ pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     let decimals = ctx.accounts.mint.decimals;
     token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
 
-    if bank.total_borrowed == 0 {
-        bank.total_borrowed = amount;
-        bank.total_borrowed_shares = amount;
-    }
-    
-    let borrow_ratio: u64 = amount.checked_div(bank.total_borrowed).unwrap();
-    let user_borrow_shares: u64 = bank.total_borrowed_shares.checked_mul(borrow_ratio).unwrap();
+    // Debt shares are minted rounding up, so a borrower never owes less than they took
+    let user_borrow_shares = amount_to_shares(debt, bank.total_borrowed, bank.total_borrowed_shares, Rounding::Up)
+        .ok_or(LendingError::MathOverflow)?;
     
     match ctx.accounts.mint.to_account_info().key() {
         key if key == user.usdc_address => {
-            user.borrowed_usdc += amount;
-            user.borrowed_usdc_shares += user_borrow_shares;
+            user.borrowed_usdc = user.borrowed_usdc
+                .checked_add(debt)
+                .ok_or(LendingError::MathOverflow)?;
+            user.borrowed_usdc_shares = user.borrowed_usdc_shares
+                .checked_add(user_borrow_shares)
+                .ok_or(LendingError::MathOverflow)?;
         }
         _ => {
-            user.borrowed_sol += amount;
-            user.borrowed_sol_shares += user_borrow_shares;
+            user.borrowed_sol = user.borrowed_sol
+                .checked_add(debt)
+                .ok_or(LendingError::MathOverflow)?;
+            user.borrowed_sol_shares = user.borrowed_sol_shares
+                .checked_add(user_borrow_shares)
+                .ok_or(LendingError::MathOverflow)?;
         }
     }
     
-    user.last_updated_borrow = Clock::get()?.unix_timestamp;
-    bank.total_borrowed += amount;
-    bank.total_borrowed_shares += user_borrow_shares;
+    user.last_updated_borrow = now;
+    bank.total_borrowed = bank.total_borrowed
+        .checked_add(debt)
+        .ok_or(LendingError::MathOverflow)?;
+    bank.total_borrowed_shares = bank.total_borrowed_shares
+        .checked_add(user_borrow_shares)
+        .ok_or(LendingError::MathOverflow)?;
+    // The fee is only in the treasury once repaid, so it reaches protocol reserves on repayment
+    bank.uncollected_origination_fees = bank.uncollected_origination_fees
+        .checked_add(origination_fee)
+        .ok_or(LendingError::MathOverflow)?;
+
+    emit_cpi!(Borrowed {
+        user: user.owner,
+        bank: bank.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        origination_fee,
+        shares: user_borrow_shares,
+        total_borrowed: bank.total_borrowed,
+        total_borrowed_shares: bank.total_borrowed_shares,
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        price: bank_price.price,
+        price_exponent: bank_price.exponent,
+        price_source: bank_price.source,
+        collateral_price: collateral_price.price,
+        collateral_price_exponent: collateral_price.exponent,
+        collateral_price_source: collateral_price.source,
+        timestamp: user.last_updated_borrow,
+    });
     
     Ok(())
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
-use crate::state::*;
+use crate::{error::LendingError, events::Deposited, state::*};
 use anchor_spl::{associated_token::AssociatedToken, token_interface::TransferChecked, token_interface::{self, Mint, TokenAccount, TokenInterface}};
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Deposit<'info>{
     #[account(mut)]
@@ -22,6 +23,7 @@ pub bank_token_account:InterfaceAccount<'info,TokenAccount>,
     mut,
     seeds=[signer.key().as_ref()],
     bump,
+    constraint = user_account.other_mint(mint.key()).is_some() @ LendingError::PositionMintMismatch,
 )]
 pub user_account:Account<'info,User>,
 
@@ -43,6 +45,8 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
     
+    bank.require_no_active_flash_loan()?;
+    
     // Update bank interest rates before deposit
     bank.update_interest()?;
     
@@ -58,19 +62,9 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     let decimals: u8 = ctx.accounts.mint.decimals;
     token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
     
-    // Calculate shares for the user
-    let user_shares = if bank.total_deposits == 0 || bank.total_deposits_shares == 0 {
-        // First deposit - shares equal to amount
-        amount
-    } else {
-        // Calculate shares based on current exchange rate
-        // user_shares = amount * total_deposits_shares / total_deposits
-        amount
-            .checked_mul(bank.total_deposits_shares)
-            .ok_or(LendingError::MathOverflow)?
-            .checked_div(bank.total_deposits)
-            .ok_or(LendingError::MathOverflow)?
-    };
+    // Calculate shares for the user at the current exchange rate, rounding down
+    let user_shares = amount_to_shares(amount, bank.total_deposits, bank.total_deposits_shares, Rounding::Down)
+        .ok_or(LendingError::MathOverflow)?;
     
     // Update user balances based on mint type
     match ctx.accounts.mint.to_account_info().key() {
@@ -105,12 +99,19 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     
     // Update interest rates after deposit
     bank.update_interest()?;
+
+    emit_cpi!(Deposited {
+        user: user.owner,
+        bank: bank.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: user_shares,
+        total_deposits: bank.total_deposits,
+        total_deposits_shares: bank.total_deposits_shares,
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        timestamp: user.last_updated,
+    });
     
     Ok(())
 }
-
-#[error_code]
-pub enum LendingError {
-    #[msg("Math overflow")]
-    MathOverflow,
-}
\ No newline at end of file
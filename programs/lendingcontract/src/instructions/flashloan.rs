@@ -1,7 +1,35 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, Discriminator, InstructionData};
+use anchor_lang::solana_program::{instruction::{AccountMeta, Instruction}, program::invoke};
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked}};
-use crate::state::*;
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use crate::{error::LendingError, events::*, instruction as ix, state::*};
 
+// Positions in the repay_flash_loan account list, checked against the initiating instruction
+const REPAY_BORROWER_INDEX: usize = 0;
+const REPAY_MINT_INDEX: usize = 1;
+const REPAY_BANK_INDEX: usize = 2;
+
+// Anchor discriminator of `on_flash_loan`, the instruction a flash loan receiver exposes
+pub const ON_FLASH_LOAN_DISCRIMINATOR: [u8; 8] = [195, 212, 238, 236, 80, 204, 73, 167];
+
+// Anchor discriminator of `on_multi_flash_loan`, called by flash_loan_multi
+pub const ON_MULTI_FLASH_LOAN_DISCRIMINATOR: [u8; 8] = [203, 79, 130, 155, 149, 217, 60, 27];
+
+// Accounts per asset in flash_loan_multi: mint, bank, treasury, receiver token account
+const MULTI_FLASH_LOAN_ACCOUNTS: usize = 4;
+
+/// Arguments passed to the receiver's `on_flash_loan` instruction. By the time it returns,
+/// the receiver must have sent `amount + fee` back to the treasury.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FlashLoanCallback {
+    pub initiator: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub data: Vec<u8>,
+}
+
+#[event_cpi]
 #[derive(Accounts)]
 pub struct InitiateFlashLoan<'info> {
     #[account(mut)]
@@ -42,8 +70,19 @@ pub struct InitiateFlashLoan<'info> {
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, used to find the matching repay_flash_loan
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    
+    // Present only for fee-exempt keepers
+    #[account(
+        seeds = [b"fee_exempt", borrower.key().as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct RepayFlashLoan<'info> {
     #[account(mut)]
@@ -62,8 +101,8 @@ pub struct RepayFlashLoan<'info> {
         mut,
         seeds = [b"flash_loan", borrower.key().as_ref(), mint.key().as_ref()],
         bump,
-        constraint = flash_loan.is_active @ ErrorCode::FlashLoanNotActive,
-        constraint = flash_loan.borrower == borrower.key() @ ErrorCode::UnauthorizedFlashLoan,
+        constraint = flash_loan.is_active @ LendingError::FlashLoanNotActive,
+        constraint = flash_loan.borrower == borrower.key() @ LendingError::UnauthorizedFlashLoan,
         close = borrower
     )]
     pub flash_loan: Account<'info, FlashLoan>,
@@ -88,6 +127,86 @@ pub struct RepayFlashLoan<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FlashLoanAsset {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Arguments passed to the receiver's `on_multi_flash_loan` instruction. `fees[i]` is owed
+/// on top of `assets[i].amount`, each to its own bank's treasury.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct MultiFlashLoanCallback {
+    pub initiator: Pubkey,
+    pub assets: Vec<FlashLoanAsset>,
+    pub fees: Vec<u64>,
+    pub data: Vec<u8>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteFlashLoan<'info> {
+    pub borrower: Signer<'info>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump
+    )]
+    pub bank: Account<'info, Bank>,
+    
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+    )]
+    pub receiver_token_account: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: Program called back with `on_flash_loan`; only needs to be executable
+    #[account(executable)]
+    pub receiver_program: UncheckedAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    // Present only for fee-exempt keepers
+    #[account(
+        seeds = [b"fee_exempt", borrower.key().as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
+    // remaining_accounts: passed through, in order, to the receiver's on_flash_loan
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteMultiFlashLoan<'info> {
+    pub borrower: Signer<'info>,
+    
+    /// CHECK: Program called back with `on_multi_flash_loan`; only needs to be executable
+    #[account(executable)]
+    pub receiver_program: UncheckedAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    // Present only for fee-exempt keepers
+    #[account(
+        seeds = [b"fee_exempt", borrower.key().as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
+    // remaining_accounts: per asset, [mint, bank (writable), treasury (writable),
+    // receiver token account (writable)], then everything passed through to the receiver
+}
+
 pub fn initiate_flash_loan(
     ctx: Context<InitiateFlashLoan>,
     amount: u64,
@@ -96,15 +215,27 @@ pub fn initiate_flash_loan(
     let flash_loan = &mut ctx.accounts.flash_loan;
     let mint_key = ctx.accounts.mint.key();
     
+    bank.require_no_active_flash_loan()?;
+    
     // Update bank interest before processing
     bank.update_interest()?;
     
-    // Check if bank has sufficient liquidity
-    let available_liquidity = ctx.accounts.bank_token_account.amount;
-    require!(available_liquidity >= amount, ErrorCode::InsufficientLiquidity);
+    require_repayment_in_transaction(
+        &ctx.accounts.instructions.to_account_info(),
+        &ctx.accounts.borrower.key(),
+        &mint_key,
+        &bank.key(),
+        amount,
+    )?;
+    
+    // Protocol reserves are not lendable, same as for flash_loan
+    require!(
+        amount <= bank.available_liquidity(ctx.accounts.bank_token_account.amount),
+        LendingError::InsufficientLiquidity
+    );
     
     // Calculate flash loan fee
-    let fee = bank.calculate_flash_loan_fee(amount);
+    let fee = bank.calculate_flash_loan_fee(amount, ctx.accounts.fee_exemption.is_some())?;
     
     // Initialize flash loan state
     flash_loan.borrower = ctx.accounts.borrower.key();
@@ -115,6 +246,9 @@ pub fn initiate_flash_loan(
     flash_loan.is_active = true;
     flash_loan.created_at = Clock::get()?.unix_timestamp;
     
+    // Lock the bank until repay_flash_loan, which the check above guarantees is in this transaction
+    bank.flash_loan_active = true;
+    
     // Transfer tokens from bank treasury to borrower account
     let transfer_cpi_acc = TransferChecked {
         from: ctx.accounts.bank_token_account.to_account_info(),
@@ -139,7 +273,7 @@ pub fn initiate_flash_loan(
     msg!("Fee: {} tokens", fee);
     
     // Emit event for flash loan initiated
-    emit!(FlashLoanInitiated {
+    emit_cpi!(FlashLoanInitiated {
         borrower: ctx.accounts.borrower.key(),
         bank: bank.key(),
         mint: mint_key,
@@ -151,23 +285,23 @@ pub fn initiate_flash_loan(
     Ok(())
 }
 
-pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>) -> Result<()> {
+pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>, amount: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let flash_loan = &ctx.accounts.flash_loan;
-    
-    // Check that flash loan is within same transaction (security measure)
     let current_time = Clock::get()?.unix_timestamp;
-    require!(
-        current_time == flash_loan.created_at,
-        ErrorCode::FlashLoanMustBeRepaidInSameTransaction
-    );
+    // Only set by initiate_flash_loan here: flash_loan and initiate_flash_loan exclude each other
+    require!(bank.flash_loan_active, LendingError::FlashLoanNotActive);
+    
+    // initiate_flash_loan only succeeds when this instruction follows it in the same
+    // transaction, so an open flash loan can never outlive its transaction
+    require!(amount == flash_loan.amount, LendingError::FlashLoanAmountMismatch);
     
-    let total_repayment = flash_loan.amount + flash_loan.fee;
+    let total_repayment = flash_loan.amount.checked_add(flash_loan.fee).ok_or(LendingError::MathOverflow)?;
     
     // Check borrower has sufficient balance
     require!(
         ctx.accounts.borrower_token_account.amount >= total_repayment,
-        ErrorCode::InsufficientBalanceForRepayment
+        LendingError::InsufficientBalanceForRepayment
     );
     
     // Transfer repayment from borrower to bank treasury
@@ -183,14 +317,15 @@ pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>) -> Result<()> {
     let decimals = ctx.accounts.mint.decimals;
     anchor_spl::token_interface::transfer_checked(cpi_ctx, total_repayment, decimals)?;
     
-    // Update bank's total deposits with the fee earned
-    bank.total_deposits = bank.total_deposits.checked_add(flash_loan.fee).unwrap();
+    // Split the fee earned between depositors and protocol reserves
+    bank.credit_flash_loan_fee(flash_loan.fee)?;
+    bank.flash_loan_active = false;
     
     msg!("Flash loan repaid: {} + {} fee = {} total", 
          flash_loan.amount, flash_loan.fee, total_repayment);
     
     // Emit event for flash loan repaid
-    emit!(FlashLoanRepaid {
+    emit_cpi!(FlashLoanRepaid {
         borrower: ctx.accounts.borrower.key(),
         bank: bank.key(),
         mint: flash_loan.mint,
@@ -202,38 +337,269 @@ pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>) -> Result<()> {
     Ok(())
 }
 
-// Events
-#[event]
-pub struct FlashLoanInitiated {
-    pub borrower: Pubkey,
-    pub bank: Pubkey,
-    pub mint: Pubkey,
-    pub amount: u64,
-    pub fee: u64,
-    pub timestamp: i64,
+pub fn flash_loan<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteFlashLoan<'info>>,
+    amount: u64,
+    data: Vec<u8>,
+) -> Result<()> {
+    let receiver_program = ctx.accounts.receiver_program.key();
+    require_keys_neq!(receiver_program, crate::ID, LendingError::InvalidFlashLoanReceiver);
+    let mint_key = ctx.accounts.mint.key();
+    let balance_before = ctx.accounts.bank_token_account.amount;
+    
+    let fee = {
+        let bank = &mut ctx.accounts.bank;
+        bank.require_no_active_flash_loan()?;
+        bank.update_interest()?;
+        require!(amount <= bank.available_liquidity(balance_before), LendingError::InsufficientLiquidity);
+        
+        // Persist the guard before handing control to the receiver, so any call back
+        // into this bank during the callback sees it
+        bank.flash_loan_active = true;
+        bank.calculate_flash_loan_fee(amount, ctx.accounts.fee_exemption.is_some())?
+    };
+    ctx.accounts.bank.exit(&crate::ID)?;
+    
+    // Transfer tokens from bank treasury to the receiver
+    let transfer_cpi_acc = TransferChecked {
+        from: ctx.accounts.bank_token_account.to_account_info(),
+        to: ctx.accounts.receiver_token_account.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+    };
+    
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let signer_seeds: &[&[&[u8]]] = &[
+        &[
+            b"treasury",
+            mint_key.as_ref(),
+            &[ctx.bumps.bank_token_account],
+        ]
+    ];
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_acc).with_signer(signer_seeds);
+    let decimals = ctx.accounts.mint.decimals;
+    anchor_spl::token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+    
+    // Hand control to the receiver, which must send amount + fee back before returning
+    let mut callback_data = ON_FLASH_LOAN_DISCRIMINATOR.to_vec();
+    FlashLoanCallback {
+        initiator: ctx.accounts.borrower.key(),
+        mint: mint_key,
+        amount,
+        fee,
+        data,
+    }
+    .serialize(&mut callback_data)?;
+    invoke_receiver(&ctx.accounts.receiver_program, ctx.remaining_accounts, callback_data)?;
+    
+    ctx.accounts.bank_token_account.reload()?;
+    let required_balance = balance_before.checked_add(fee).ok_or(LendingError::MathOverflow)?;
+    require!(
+        ctx.accounts.bank_token_account.amount >= required_balance,
+        LendingError::FlashLoanNotRepaid
+    );
+    
+    // The callback may have changed the bank (price updates, admin setters), so re-read it and
+    // apply only the flash loan's own changes on top
+    let bank = &mut ctx.accounts.bank;
+    bank.reload()?;
+    // Split the fee earned between depositors and protocol reserves
+    bank.credit_flash_loan_fee(fee)?;
+    bank.flash_loan_active = false;
+    
+    msg!("Flash loan of {} repaid through {} with {} fee", amount, receiver_program, fee);
+    
+    emit_cpi!(FlashLoanExecuted {
+        borrower: ctx.accounts.borrower.key(),
+        bank: bank.key(),
+        mint: mint_key,
+        receiver_program,
+        amount,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(())
 }
 
-#[event]
-pub struct FlashLoanRepaid {
-    pub borrower: Pubkey,
-    pub bank: Pubkey,
-    pub mint: Pubkey,
-    pub amount: u64,
-    pub fee: u64,
-    pub timestamp: i64,
+struct FlashLoanLeg<'info> {
+    bank: Account<'info, Bank>,
+    treasury: InterfaceAccount<'info, TokenAccount>,
+    balance_before: u64,
+    amount: u64,
+    fee: u64,
+}
+
+pub fn flash_loan_multi<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteMultiFlashLoan<'info>>,
+    assets: Vec<FlashLoanAsset>,
+    data: Vec<u8>,
+) -> Result<()> {
+    let receiver_program = ctx.accounts.receiver_program.key();
+    require_keys_neq!(receiver_program, crate::ID, LendingError::InvalidFlashLoanReceiver);
+    let loan_accounts_len = assets.len() * MULTI_FLASH_LOAN_ACCOUNTS;
+    require!(
+        !assets.is_empty() && ctx.remaining_accounts.len() >= loan_accounts_len,
+        LendingError::InvalidFlashLoanAssets
+    );
+    let (loan_accounts, callback_accounts) = ctx.remaining_accounts.split_at(loan_accounts_len);
+    let token_program = ctx.accounts.token_program.to_account_info();
+    
+    let mut legs = Vec::with_capacity(assets.len());
+    for (asset, accounts) in assets.iter().zip(loan_accounts.chunks(MULTI_FLASH_LOAN_ACCOUNTS)) {
+        let [mint_info, bank_info, treasury_info, receiver_token_info] = accounts else {
+            return err!(LendingError::InvalidFlashLoanAssets);
+        };
+        let (bank_key, _) = Pubkey::find_program_address(&[asset.mint.as_ref()], ctx.program_id);
+        let (treasury_key, treasury_bump) = Pubkey::find_program_address(
+            &[b"treasury", asset.mint.as_ref()],
+            ctx.program_id,
+        );
+        require_keys_eq!(mint_info.key(), asset.mint, LendingError::InvalidFlashLoanAssets);
+        require_keys_eq!(bank_info.key(), bank_key, LendingError::InvalidFlashLoanAssets);
+        require_keys_eq!(treasury_info.key(), treasury_key, LendingError::InvalidFlashLoanAssets);
+        
+        let mint: InterfaceAccount<Mint> = InterfaceAccount::try_from(mint_info)?;
+        let mut bank: Account<Bank> = Account::try_from(bank_info)?;
+        let treasury: InterfaceAccount<TokenAccount> = InterfaceAccount::try_from(treasury_info)?;
+        let receiver_token_account: InterfaceAccount<TokenAccount> = InterfaceAccount::try_from(receiver_token_info)?;
+        require_keys_eq!(receiver_token_account.mint, asset.mint, LendingError::InvalidFlashLoanAssets);
+        
+        // The guard is persisted right away, so listing the same bank twice fails here
+        bank.require_no_active_flash_loan()?;
+        bank.update_interest()?;
+        let balance_before = treasury.amount;
+        require!(asset.amount <= bank.available_liquidity(balance_before), LendingError::InsufficientLiquidity);
+        let fee = bank.calculate_flash_loan_fee(asset.amount, ctx.accounts.fee_exemption.is_some())?;
+        bank.flash_loan_active = true;
+        bank.exit(ctx.program_id)?;
+        
+        let transfer_cpi_acc = TransferChecked {
+            from: treasury_info.clone(),
+            to: receiver_token_info.clone(),
+            authority: treasury_info.clone(),
+            mint: mint_info.clone(),
+        };
+        let signer_seeds: &[&[&[u8]]] = &[
+            &[
+                b"treasury",
+                asset.mint.as_ref(),
+                &[treasury_bump],
+            ]
+        ];
+        let cpi_ctx = CpiContext::new(token_program.clone(), transfer_cpi_acc).with_signer(signer_seeds);
+        anchor_spl::token_interface::transfer_checked(cpi_ctx, asset.amount, mint.decimals)?;
+        
+        legs.push(FlashLoanLeg { bank, treasury, balance_before, amount: asset.amount, fee });
+    }
+    
+    let mut callback_data = ON_MULTI_FLASH_LOAN_DISCRIMINATOR.to_vec();
+    MultiFlashLoanCallback {
+        initiator: ctx.accounts.borrower.key(),
+        fees: legs.iter().map(|leg| leg.fee).collect(),
+        assets,
+        data,
+    }
+    .serialize(&mut callback_data)?;
+    invoke_receiver(&ctx.accounts.receiver_program, callback_accounts, callback_data)?;
+    
+    // Each bank must get its own principal plus fee back; a surplus on one can't cover another
+    let timestamp = Clock::get()?.unix_timestamp;
+    for leg in legs.iter_mut() {
+        leg.treasury.reload()?;
+        let required_balance = leg.balance_before.checked_add(leg.fee).ok_or(LendingError::MathOverflow)?;
+        require!(leg.treasury.amount >= required_balance, LendingError::FlashLoanNotRepaid);
+        
+        // Re-read past any changes made during the callback before crediting the fee
+        let bank = &mut leg.bank;
+        bank.reload()?;
+        bank.credit_flash_loan_fee(leg.fee)?;
+        bank.flash_loan_active = false;
+        bank.exit(ctx.program_id)?;
+        
+        msg!("Flash loan of {} {} repaid with {} fee", leg.amount, bank.mint_address, leg.fee);
+        
+        emit_cpi!(FlashLoanExecuted {
+            borrower: ctx.accounts.borrower.key(),
+            bank: bank.key(),
+            mint: bank.mint_address,
+            receiver_program,
+            amount: leg.amount,
+            fee: leg.fee,
+            timestamp,
+        });
+    }
+    
+    Ok(())
+}
+
+/// Calls the flash loan receiver, forwarding `accounts` with their signer and writable flags.
+fn invoke_receiver<'info>(
+    receiver_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let callback_ix = Instruction {
+        program_id: receiver_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut callback_accounts = accounts.to_vec();
+    callback_accounts.push(receiver_program.clone());
+    invoke(&callback_ix, &callback_accounts)?;
+    Ok(())
 }
 
-// Error codes
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Insufficient liquidity in the bank")]
-    InsufficientLiquidity,
-    #[msg("Flash loan is not active")]
-    FlashLoanNotActive,
-    #[msg("Unauthorized to repay this flash loan")]
-    UnauthorizedFlashLoan,
-    #[msg("Flash loan must be repaid within the same transaction")]
-    FlashLoanMustBeRepaidInSameTransaction,
-    #[msg("Insufficient balance for repayment")]
-    InsufficientBalanceForRepayment,
-}
\ No newline at end of file
+/// Checks the transaction's instruction list so a flash loan can only be opened when it is
+/// closed again before the transaction ends.
+///
+/// The initiating instruction must be top level (not reached through CPI), and a
+/// `repay_flash_loan` for the same borrower, bank, mint and amount must follow it, with no
+/// other `initiate_flash_loan` in between.
+fn require_repayment_in_transaction(
+    instructions: &AccountInfo,
+    borrower: &Pubkey,
+    mint: &Pubkey,
+    bank: &Pubkey,
+    amount: u64,
+) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let current_ix = load_instruction_at_checked(current_index, instructions)?;
+    require!(
+        current_ix.program_id == crate::ID && current_ix.data.starts_with(ix::InitiateFlashLoan::DISCRIMINATOR),
+        LendingError::FlashLoanCpiNotAllowed
+    );
+
+    let repay_data = ix::RepayFlashLoan { amount }.data();
+    let mut index = current_index + 1;
+    while let Ok(next_ix) = load_instruction_at_checked(index, instructions) {
+        index += 1;
+        if next_ix.program_id != crate::ID {
+            continue;
+        }
+        require!(
+            !next_ix.data.starts_with(ix::InitiateFlashLoan::DISCRIMINATOR),
+            LendingError::NestedFlashLoan
+        );
+        if !next_ix.data.starts_with(ix::RepayFlashLoan::DISCRIMINATOR) {
+            continue;
+        }
+
+        let account_is = |position: usize, key: &Pubkey| {
+            next_ix.accounts.get(position).is_some_and(|meta| meta.pubkey == *key)
+        };
+        if account_is(REPAY_BORROWER_INDEX, borrower) && account_is(REPAY_MINT_INDEX, mint) && account_is(REPAY_BANK_INDEX, bank) {
+            require!(next_ix.data == repay_data, LendingError::FlashLoanAmountMismatch);
+            return Ok(());
+        }
+    }
+
+    err!(LendingError::FlashLoanMustBeRepaidInSameTransaction)
+}
@@ -1,7 +1,17 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::Mint;
-use crate::state::*;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked};
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use crate::{constants::PARAM_UNCHANGED, error::LendingError, events::*, state::*};
 
+// Layout of the Ed25519 precompile instruction data
+const ED25519_HEADER_LEN: usize = 2;
+const ED25519_OFFSETS_LEN: usize = 14;
+const ED25519_PUBKEY_LEN: usize = 32;
+// Signed vote message: proposal pubkey followed by a single choice byte (1 = for, 0 = against)
+const SIGNED_VOTE_MESSAGE_LEN: usize = 33;
+
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(proposal_id: u64)]
 pub struct CreateProposal<'info> {
@@ -25,18 +35,45 @@ pub struct CreateProposal<'info> {
     )]
     pub proposal: Account<'info, Proposal>,
     
-    // Proposer must be a valid user with some stake/deposit to avoid spam
+    // Proposer must hold voting power to avoid spam: deposit shares by default,
+    // or a vote lock once the bank has a governance mint
     #[account(
         seeds = [proposer.key().as_ref()],
         bump,
-        // FIX: Use constraint because 'user_account.owner' does not match 'proposer' name
-        constraint = user_account.owner == proposer.key(),
     )]
-    pub user_account: Account<'info, User>,
-    
+    pub user_account: Option<Account<'info, User>>,
+
+    #[account(
+        seeds = [b"vote_lock", bank.governance_mint.as_ref(), proposer.key().as_ref()],
+        bump,
+    )]
+    pub vote_lock: Option<Account<'info, VoteLock>>,
+
+    // Escrow holding the proposer's bond until the proposal is settled
+    #[account(
+        init,
+        payer = proposer,
+        token::mint = mint,
+        token::authority = bond_escrow,
+        token::token_program = token_program,
+        seeds = [b"proposal_bond", proposal.key().as_ref()],
+        bump,
+    )]
+    pub bond_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = proposer,
+        token::token_program = token_program,
+    )]
+    pub proposer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Vote<'info> {
     #[account(mut)]
@@ -44,6 +81,9 @@ pub struct Vote<'info> {
     
     #[account(mut)]
     pub proposal: Account<'info, Proposal>,
+
+    #[account(constraint = proposal.bank == bank.key())]
+    pub bank: Account<'info, Bank>,
     
     #[account(
         init,
@@ -58,11 +98,88 @@ pub struct Vote<'info> {
         seeds = [voter.key().as_ref()],
         bump,
     )]
-    pub user_account: Account<'info, User>,
+    pub user_account: Option<Account<'info, User>>,
+
+    #[account(
+        seeds = [b"vote_lock", bank.governance_mint.as_ref(), voter.key().as_ref()],
+        bump,
+    )]
+    pub vote_lock: Option<Account<'info, VoteLock>>,
     
     pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SettleProposalBond<'info> {
+    pub settler: Signer<'info>, // Anyone can settle once voting has ended
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = proposal.bank == bank.key(),
+        constraint = !proposal.bond_settled @ LendingError::BondAlreadySettled,
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal_bond", proposal.key().as_ref()],
+        bump,
+    )]
+    pub bond_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = proposal.proposer,
+        token::token_program = token_program,
+    )]
+    pub proposer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Receives the escrow's rent when it is closed
+    #[account(mut, address = proposal.proposer)]
+    pub proposer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SubmitSignedVotes<'info> {
+    // Relayer submitting the batch; pays rent for the voters' vote records
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    #[account(mut)]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(constraint = proposal.bank == bank.key())]
+    pub bank: Account<'info, Bank>,
+
+    /// CHECK: Instructions sysvar, used to read the Ed25519 verification instruction
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    // remaining_accounts: per signature, [voter user_account or vote_lock, voter vote_record PDA (writable)]
+}
+
+#[event_cpi]
 #[derive(Accounts)]
 pub struct ExecuteProposal<'info> {
     #[account(mut)]
@@ -84,6 +201,7 @@ pub struct ExecuteProposal<'info> {
     pub proposal: Account<'info, Proposal>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_proposal(
     ctx: Context<CreateProposal>, 
     proposal_id: u64,
@@ -98,9 +216,31 @@ pub fn create_proposal(
     let proposal = &mut ctx.accounts.proposal;
     let current_time = Clock::get()?.unix_timestamp;
     
-    // Anti-spam: Require some deposit share to propose (e.g., > 0)
-    let user = &ctx.accounts.user_account;
-    require!(user.deposited_sol_shares > 0 || user.deposited_usdc_shares > 0, GovernanceError::InsufficientStake);
+    // Anti-spam: Require a minimum voting power and a bond to propose
+    let bank = &ctx.accounts.bank;
+    let proposer_power = voting_power(
+        bank,
+        &ctx.accounts.proposer.key(),
+        ctx.accounts.user_account.as_deref(),
+        ctx.accounts.vote_lock.as_deref(),
+        current_time + duration,
+    )?;
+    require!(
+        proposer_power > 0 && proposer_power >= bank.min_proposer_power,
+        LendingError::InsufficientStake
+    );
+
+    let bond_amount = bank.proposal_bond;
+    if bond_amount > 0 {
+        let transfer_cpi_acc = TransferChecked {
+            from: ctx.accounts.proposer_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.bond_escrow.to_account_info(),
+            authority: ctx.accounts.proposer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_cpi_acc);
+        token_interface::transfer_checked(cpi_ctx, bond_amount, ctx.accounts.mint.decimals)?;
+    }
 
     proposal.id = proposal_id;
     proposal.proposer = ctx.accounts.proposer.key();
@@ -117,78 +257,450 @@ pub fn create_proposal(
     proposal.created_at = current_time;
     proposal.end_time = current_time + duration;
     proposal.executed = false;
+    proposal.bond_amount = bond_amount;
+    proposal.bond_settled = false;
     
     msg!("Proposal {} created. Ends at {}", proposal_id, proposal.end_time);
+
+    emit_cpi!(ProposalCreated {
+        proposal: proposal.key(),
+        bank: proposal.bank,
+        proposer: proposal.proposer,
+        id: proposal_id,
+        proposal_type,
+        params: [param_1, param_2, param_3, param_4, param_5],
+        bond_amount,
+        end_time: proposal.end_time,
+    });
     Ok(())
 }
 
 pub fn cast_vote(ctx: Context<Vote>, vote_for: bool) -> Result<()> {
     let proposal = &mut ctx.accounts.proposal;
-    let user = &ctx.accounts.user_account;
     let vote_record = &mut ctx.accounts.vote_record;
     
-    require!(Clock::get()?.unix_timestamp < proposal.end_time, GovernanceError::VotingEnded);
+    require!(Clock::get()?.unix_timestamp < proposal.end_time, LendingError::VotingEnded);
     
-    // Calculate voting power based on shares held
-    // Simple Strategy: 1 share = 1 vote (combining SOL and USDC shares roughly)
-    let voting_power = user.deposited_sol_shares + user.deposited_usdc_shares;
-    require!(voting_power > 0, GovernanceError::InsufficientStake);
-
-    if vote_for {
-        proposal.votes_for = proposal.votes_for.checked_add(voting_power).unwrap();
-    } else {
-        proposal.votes_against = proposal.votes_against.checked_add(voting_power).unwrap();
-    }
+    let voting_power = voting_power(
+        &ctx.accounts.bank,
+        &ctx.accounts.voter.key(),
+        ctx.accounts.user_account.as_deref(),
+        ctx.accounts.vote_lock.as_deref(),
+        proposal.end_time,
+    )?;
+    tally_vote(proposal, voting_power, vote_for)?;
     
     vote_record.proposal = proposal.key();
     vote_record.voter = ctx.accounts.voter.key();
     vote_record.voted = true;
     
     msg!("Vote cast. Power: {}", voting_power);
+
+    emit_cpi!(VoteCast {
+        proposal: proposal.key(),
+        voter: vote_record.voter,
+        vote_for,
+        voting_power,
+        signed: false,
+        votes_for: proposal.votes_for,
+        votes_against: proposal.votes_against,
+    });
     Ok(())
 }
 
+pub fn submit_signed_votes<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SubmitSignedVotes<'info>>,
+) -> Result<()> {
+    let proposal = &mut ctx.accounts.proposal;
+    let proposal_key = proposal.key();
+    require!(Clock::get()?.unix_timestamp < proposal.end_time, LendingError::VotingEnded);
+
+    // The relayer places one Ed25519 verify instruction directly before this one.
+    // The runtime has already checked every signature in it, so we only need to
+    // read back which keys signed which messages.
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let current_index = load_current_index_checked(&instructions)? as usize;
+    require!(current_index > 0, LendingError::InvalidSignatureInstruction);
+    let ed25519_ix = load_instruction_at_checked(current_index - 1, &instructions)?;
+    require_keys_eq!(
+        ed25519_ix.program_id,
+        solana_sdk_ids::ed25519_program::ID,
+        LendingError::InvalidSignatureInstruction
+    );
+    let signed_votes = parse_signed_votes(&ed25519_ix.data)?;
+    require!(!signed_votes.is_empty(), LendingError::InvalidSignatureInstruction);
+
+    let remaining = ctx.remaining_accounts;
+    require!(
+        remaining.len() == signed_votes.len() * 2,
+        LendingError::SignedVoteAccountsMismatch
+    );
+
+    for (i, (voter, message)) in signed_votes.iter().enumerate() {
+        require!(
+            message[..32] == proposal_key.to_bytes(),
+            LendingError::InvalidSignedVoteMessage
+        );
+        let vote_for = match message[32] {
+            0 => false,
+            1 => true,
+            _ => return err!(LendingError::InvalidSignedVoteMessage),
+        };
+
+        let power_info = &remaining[i * 2];
+        let vote_record_info = &remaining[i * 2 + 1];
+
+        let voting_power = if ctx.accounts.bank.governance_mint == Pubkey::default() {
+            let user: Account<User> = Account::try_from(power_info)?;
+            voting_power(&ctx.accounts.bank, voter, Some(&user), None, proposal.end_time)?
+        } else {
+            let vote_lock: Account<VoteLock> = Account::try_from(power_info)?;
+            voting_power(&ctx.accounts.bank, voter, None, Some(&vote_lock), proposal.end_time)?
+        };
+
+        // Replay protection: the same vote record PDA used by `vote`, so each voter
+        // gets exactly one vote per proposal across both paths
+        let (vote_record_key, bump) = Pubkey::find_program_address(
+            &[b"vote", proposal_key.as_ref(), voter.as_ref()],
+            ctx.program_id,
+        );
+        require_keys_eq!(vote_record_info.key(), vote_record_key, LendingError::SignedVoteAccountsMismatch);
+        // Only an initialised record counts as a vote; lamports alone can be sent by anyone
+        require!(
+            vote_record_info.owner != ctx.program_id || vote_record_info.data_is_empty(),
+            LendingError::AlreadyVoted
+        );
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"vote",
+            proposal_key.as_ref(),
+            voter.as_ref(),
+            &[bump],
+        ]];
+        init_vote_record(
+            vote_record_info,
+            &ctx.accounts.relayer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            ctx.program_id,
+            signer_seeds,
+        )?;
+        let vote_record = VoteRecord {
+            proposal: proposal_key,
+            voter: *voter,
+            voted: true,
+        };
+        vote_record.try_serialize(&mut &mut vote_record_info.try_borrow_mut_data()?[..])?;
+
+        tally_vote(proposal, voting_power, vote_for)?;
+        msg!("Signed vote by {}. Power: {}", voter, voting_power);
+
+        emit_cpi!(VoteCast {
+            proposal: proposal_key,
+            voter: *voter,
+            vote_for,
+            voting_power,
+            signed: true,
+            votes_for: proposal.votes_for,
+            votes_against: proposal.votes_against,
+        });
+    }
+
+    Ok(())
+}
+
+// Creates the vote record PDA the way Anchor's `init` does, so a record pre-funded by a
+// third party is topped up and allocated instead of failing create_account
+fn init_vote_record<'info>(
+    vote_record: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    program_id: &Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let space = 8 + VoteRecord::INIT_SPACE;
+    let rent = Rent::get()?.minimum_balance(space);
+    let lamports = vote_record.lamports();
+    if lamports == 0 {
+        return system_program::create_account(
+            CpiContext::new(
+                system_program.clone(),
+                CreateAccount { from: payer.clone(), to: vote_record.clone() },
+            )
+            .with_signer(signer_seeds),
+            rent,
+            space as u64,
+            program_id,
+        );
+    }
+
+    if lamports < rent {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                Transfer { from: payer.clone(), to: vote_record.clone() },
+            ),
+            rent - lamports,
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new(system_program.clone(), Allocate { account_to_allocate: vote_record.clone() })
+            .with_signer(signer_seeds),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new(system_program.clone(), Assign { account_to_assign: vote_record.clone() })
+            .with_signer(signer_seeds),
+        program_id,
+    )
+}
+
 pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
     let proposal = &mut ctx.accounts.proposal;
     let bank = &mut ctx.accounts.bank;
     
-    require!(Clock::get()?.unix_timestamp >= proposal.end_time, GovernanceError::VotingNotEnded);
-    require!(proposal.votes_for > proposal.votes_against, GovernanceError::ProposalDefeated);
+    require!(Clock::get()?.unix_timestamp >= proposal.end_time, LendingError::VotingNotEnded);
+    require!(proposal.votes_for > proposal.votes_against, LendingError::ProposalDefeated);
+    let total_votes = proposal.votes_for.checked_add(proposal.votes_against).ok_or(LendingError::MathOverflow)?;
+    require!(total_votes >= bank.quorum_votes, LendingError::QuorumNotReached);
     
     // Update Interest prior to changes
     bank.update_interest()?;
 
     if proposal.proposal_type == 1 {
         // Update Bank Config
-        // Map params: 1=thresh, 2=bonus, 3=close_factor, 4=max_ltv
-        if proposal.param_1 > 0 { bank.liquidation_threshold = proposal.param_1; }
-        if proposal.param_2 > 0 { bank.liquidation_bonus = proposal.param_2; }
-        if proposal.param_3 > 0 { bank.liquidation_close_factor = proposal.param_3; }
-        if proposal.param_4 > 0 { bank.max_ltv = proposal.param_4; }
+        // Map params (all bps): 1=thresh, 2=bonus, 3=close_factor, 4=max_ltv, 5=min_liquidity_buffer
+        if proposal.param_1 > 0 { bank.liquidation_threshold = Bps(proposal.param_1); }
+        if proposal.param_2 > 0 { bank.liquidation_bonus = Bps(proposal.param_2); }
+        if proposal.param_3 > 0 { bank.liquidation_close_factor = Bps(proposal.param_3); }
+        if proposal.param_4 > 0 { bank.max_ltv = Bps(proposal.param_4); }
+        if proposal.param_5 > 0 { bank.min_liquidity_buffer = Bps(proposal.param_5); }
         msg!("Bank Config Updated via Governance");
+        emit_cpi!(BankConfigUpdated {
+            bank: bank.key(),
+            updated_by: proposal.key(),
+            liquidation_threshold: bank.liquidation_threshold.0,
+            liquidation_bonus: bank.liquidation_bonus.0,
+            liquidation_close_factor: bank.liquidation_close_factor.0,
+            max_ltv: bank.max_ltv.0,
+            min_liquidity_buffer: bank.min_liquidity_buffer.0,
+            origination_fee: bank.origination_fee.0,
+        });
     } else if proposal.proposal_type == 2 {
         // Update Kink Params
         // Map params: 1=base, 2=mult, 3=jump, 4=kink, 5=reserve
         if proposal.param_1 > 0 { bank.base_rate = proposal.param_1; }
         if proposal.param_2 > 0 { bank.multiplier = proposal.param_2; }
         if proposal.param_3 > 0 { bank.jump_multiplier = proposal.param_3; }
-        if proposal.param_4 > 0 { bank.kink_utilization = proposal.param_4; }
-        if proposal.param_5 > 0 { bank.reserve_factor = proposal.param_5; }
+        if proposal.param_4 > 0 { bank.kink_utilization = Bps(proposal.param_4); }
+        if proposal.param_5 > 0 { bank.reserve_factor = Bps(proposal.param_5); }
         msg!("Interest Params Updated via Governance");
+        emit_cpi!(InterestParamsUpdated {
+            bank: bank.key(),
+            updated_by: proposal.key(),
+            base_rate: bank.base_rate,
+            multiplier: bank.multiplier,
+            jump_multiplier: bank.jump_multiplier,
+            kink_utilization: bank.kink_utilization.0,
+            reserve_factor: bank.reserve_factor.0,
+        });
+    } else if proposal.proposal_type == 3 {
+        // Update Governance Params
+        // Map params: 1=bond, 2=min_proposer_power, 3=quorum
+        if proposal.param_1 > 0 { bank.proposal_bond = proposal.param_1; }
+        if proposal.param_2 > 0 { bank.min_proposer_power = proposal.param_2; }
+        if proposal.param_3 > 0 { bank.quorum_votes = proposal.param_3; }
+        msg!("Governance Params Updated via Governance");
+        emit_cpi!(GovernanceParamsUpdated {
+            bank: bank.key(),
+            updated_by: proposal.key(),
+            proposal_bond: bank.proposal_bond,
+            min_proposer_power: bank.min_proposer_power,
+            quorum_votes: bank.quorum_votes,
+        });
+    } else if proposal.proposal_type == 4 {
+        // Update Fees
+        // Map params (all bps): 1=origination_fee, 2=flash_loan_fee, 3=flash_loan_protocol_share.
+        // Fees may be set to zero, so PARAM_UNCHANGED rather than 0 leaves one as it is.
+        if proposal.param_1 != PARAM_UNCHANGED { bank.origination_fee = Bps(proposal.param_1); }
+        if proposal.param_2 != PARAM_UNCHANGED { bank.flash_loan_fee = Bps(proposal.param_2); }
+        if proposal.param_3 != PARAM_UNCHANGED { bank.flash_loan_protocol_share = Bps(proposal.param_3); }
+        msg!("Fees Updated via Governance");
+        emit_cpi!(FlashLoanFeeUpdated {
+            bank: bank.key(),
+            updated_by: proposal.key(),
+            flash_loan_fee: bank.flash_loan_fee.0,
+            flash_loan_protocol_share: bank.flash_loan_protocol_share.0,
+        });
+        emit_cpi!(BankConfigUpdated {
+            bank: bank.key(),
+            updated_by: proposal.key(),
+            liquidation_threshold: bank.liquidation_threshold.0,
+            liquidation_bonus: bank.liquidation_bonus.0,
+            liquidation_close_factor: bank.liquidation_close_factor.0,
+            max_ltv: bank.max_ltv.0,
+            min_liquidity_buffer: bank.min_liquidity_buffer.0,
+            origination_fee: bank.origination_fee.0,
+        });
     }
+    bank.validate_config()?;
     
     proposal.executed = true;
+
+    emit_cpi!(ProposalExecuted {
+        proposal: proposal.key(),
+        bank: bank.key(),
+        executor: ctx.accounts.executor.key(),
+        proposal_type: proposal.proposal_type,
+        votes_for: proposal.votes_for,
+        votes_against: proposal.votes_against,
+    });
     Ok(())
 }
 
-#[error_code]
-pub enum GovernanceError {
-    #[msg("Insufficient stake to propose or vote")]
-    InsufficientStake,
-    #[msg("Voting period has ended")]
-    VotingEnded,
-    #[msg("Voting period has not ended yet")]
-    VotingNotEnded,
-    #[msg("Proposal was defeated by votes")]
-    ProposalDefeated,
+pub fn settle_proposal_bond(ctx: Context<SettleProposalBond>) -> Result<()> {
+    let proposal = &mut ctx.accounts.proposal;
+    let bank = &mut ctx.accounts.bank;
+    // A slashed bond lands in the treasury, where it would count towards repaying a flash loan
+    bank.require_no_active_flash_loan()?;
+
+    require!(Clock::get()?.unix_timestamp >= proposal.end_time, LendingError::VotingNotEnded);
+
+    let total_votes = proposal.votes_for.checked_add(proposal.votes_against).ok_or(LendingError::MathOverflow)?;
+    let reached_quorum = total_votes >= bank.quorum_votes;
+    let bond_amount = proposal.bond_amount;
+
+    let proposal_key = proposal.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"proposal_bond",
+        proposal_key.as_ref(),
+        &[ctx.bumps.bond_escrow],
+    ]];
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+
+    if bond_amount > 0 {
+        // Bond is returned on quorum, otherwise slashed into the bank's reserves
+        let destination = if reached_quorum {
+            ctx.accounts.proposer_token_account.to_account_info()
+        } else {
+            ctx.accounts.bank_token_account.to_account_info()
+        };
+        let transfer_cpi_acc = TransferChecked {
+            from: ctx.accounts.bond_escrow.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: destination,
+            authority: ctx.accounts.bond_escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(cpi_program.clone(), transfer_cpi_acc).with_signer(signer_seeds);
+        token_interface::transfer_checked(cpi_ctx, bond_amount, ctx.accounts.mint.decimals)?;
+
+        if !reached_quorum {
+            bank.protocol_reserves = bank.protocol_reserves.checked_add(bond_amount).ok_or(LendingError::MathOverflow)?;
+        }
+    }
+
+    let close_cpi_acc = CloseAccount {
+        account: ctx.accounts.bond_escrow.to_account_info(),
+        destination: ctx.accounts.proposer.to_account_info(),
+        authority: ctx.accounts.bond_escrow.to_account_info(),
+    };
+    token_interface::close_account(CpiContext::new(cpi_program, close_cpi_acc).with_signer(signer_seeds))?;
+
+    proposal.bond_settled = true;
+
+    if reached_quorum {
+        msg!("Proposal {} reached quorum. Bond of {} returned", proposal.id, bond_amount);
+    } else {
+        msg!("Proposal {} missed quorum. Bond of {} slashed to reserves", proposal.id, bond_amount);
+    }
+
+    emit_cpi!(ProposalBondSettled {
+        proposal: proposal_key,
+        bank: bank.key(),
+        proposer: proposal.proposer,
+        bond_amount,
+        reached_quorum,
+        protocol_reserves: bank.protocol_reserves,
+    });
+    Ok(())
+}
+
+/// Voting power of `voter` for a proposal ending at `voting_ends`.
+/// Deposit shares count 1:1 unless the bank has a governance mint, in which case
+/// only a vote lock that outlasts the voting period counts.
+fn voting_power(
+    bank: &Bank,
+    voter: &Pubkey,
+    user: Option<&User>,
+    vote_lock: Option<&VoteLock>,
+    voting_ends: i64,
+) -> Result<u64> {
+    if bank.governance_mint == Pubkey::default() {
+        let user = user.ok_or(LendingError::VotingPowerAccountMissing)?;
+        require_keys_eq!(user.owner, *voter, LendingError::VotingPowerAccountMismatch);
+        Ok(user.deposited_sol_shares
+            .checked_add(user.deposited_usdc_shares)
+            .ok_or(LendingError::MathOverflow)?)
+    } else {
+        let vote_lock = vote_lock.ok_or(LendingError::VotingPowerAccountMissing)?;
+        require_keys_eq!(vote_lock.owner, *voter, LendingError::VotingPowerAccountMismatch);
+        require_keys_eq!(vote_lock.mint, bank.governance_mint, LendingError::VotingPowerAccountMismatch);
+        require!(vote_lock.lock_end >= voting_ends, LendingError::LockExpiresBeforeVotingEnds);
+        Ok(vote_lock.voting_power(Clock::get()?.unix_timestamp))
+    }
+}
+
+fn tally_vote(proposal: &mut Proposal, voting_power: u64, vote_for: bool) -> Result<()> {
+    require!(voting_power > 0, LendingError::InsufficientStake);
+    if vote_for {
+        proposal.votes_for = proposal.votes_for.checked_add(voting_power).ok_or(LendingError::MathOverflow)?;
+    } else {
+        proposal.votes_against = proposal.votes_against.checked_add(voting_power).ok_or(LendingError::MathOverflow)?;
+    }
+    Ok(())
+}
+
+/// Reads (signer, message) pairs out of Ed25519 precompile instruction data.
+/// Only signatures whose key and message live inside that same instruction are accepted.
+fn parse_signed_votes(data: &[u8]) -> Result<Vec<(Pubkey, [u8; SIGNED_VOTE_MESSAGE_LEN])>> {
+    require!(data.len() >= ED25519_HEADER_LEN, LendingError::InvalidSignatureInstruction);
+    let num_signatures = data[0] as usize;
+    let read_u16 = |at: usize| -> Result<usize> {
+        data.get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| error!(LendingError::InvalidSignatureInstruction))
+    };
+
+    let mut votes = Vec::with_capacity(num_signatures);
+    for i in 0..num_signatures {
+        let offsets = ED25519_HEADER_LEN + i * ED25519_OFFSETS_LEN;
+        let signature_ix_index = read_u16(offsets + 2)?;
+        let pubkey_offset = read_u16(offsets + 4)?;
+        let pubkey_ix_index = read_u16(offsets + 6)?;
+        let message_offset = read_u16(offsets + 8)?;
+        let message_size = read_u16(offsets + 10)?;
+        let message_ix_index = read_u16(offsets + 12)?;
+
+        // u16::MAX means "this instruction"; anything else could point the
+        // precompile at data we never see here
+        require!(
+            signature_ix_index == u16::MAX as usize
+                && pubkey_ix_index == u16::MAX as usize
+                && message_ix_index == u16::MAX as usize,
+            LendingError::InvalidSignatureInstruction
+        );
+        require!(message_size == SIGNED_VOTE_MESSAGE_LEN, LendingError::InvalidSignedVoteMessage);
+
+        let pubkey_bytes = data
+            .get(pubkey_offset..pubkey_offset + ED25519_PUBKEY_LEN)
+            .ok_or(LendingError::InvalidSignatureInstruction)?;
+        let message_bytes = data
+            .get(message_offset..message_offset + SIGNED_VOTE_MESSAGE_LEN)
+            .ok_or(LendingError::InvalidSignatureInstruction)?;
+
+        let voter = Pubkey::try_from(pubkey_bytes).map_err(|_| LendingError::InvalidSignatureInstruction)?;
+        let mut message = [0u8; SIGNED_VOTE_MESSAGE_LEN];
+        message.copy_from_slice(message_bytes);
+        votes.push((voter, message));
+    }
+    Ok(votes)
 }
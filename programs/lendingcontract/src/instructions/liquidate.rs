@@ -1,15 +1,13 @@
-use crate::{error::Lendingerror, state::*};
-use crate::{constants::{MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID}};
+use crate::{error::LendingError, events::Liquidated, instructions::refresh_prices, state::*};
 use anchor_lang::prelude::*;
 use anchor_spl::token_interface::{self, TransferChecked};
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Liquidate<'info>{
     #[account(mut)]
     pub liquidator: Signer<'info>,
-    pub price_update: Account<'info, PriceUpdateV2>,
     pub collateral_mint: InterfaceAccount<'info, Mint>,
     pub debt_mint: InterfaceAccount<'info, Mint>, 
     #[account(
@@ -21,7 +19,9 @@ pub struct Liquidate<'info>{
     #[account(
         mut,
         seeds=[debt_mint.key().as_ref()],
-        bump,)]
+        bump,
+        constraint = debt_bank.key() != collateral_bank.key(),
+    )]
     pub debt_bank: Account<'info, Bank>,
     #[account(
         mut,
@@ -36,10 +36,13 @@ pub struct Liquidate<'info>{
     )]
     pub debt_bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    /// CHECK: Owner of the position being liquidated; only used to derive its user account
+    pub borrower: UncheckedAccount<'info>,
     #[account(
         mut,
-        seeds=[liquidator.key().as_ref()],
+        seeds=[borrower.key().as_ref()],
         bump,
+        constraint = user_account.other_mint(collateral_mint.key()) == Some(debt_mint.key()) @ LendingError::PositionMintMismatch,
     )]
     pub user_account: Account<'info, User>,
 
@@ -64,128 +67,58 @@ pub struct Liquidate<'info>{
     pub system_program: Program<'info, System>,
 }
 
+// remaining_accounts: optional PriceUpdateV2 for [collateral_bank, debt_bank], each on that bank's feed
 pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
     let collateral_bank = &mut ctx.accounts.collateral_bank;
     let debt_bank = &mut ctx.accounts.debt_bank;
     let user = &mut ctx.accounts.user_account;
-    let price_update = &ctx.accounts.price_update;
+    
+    collateral_bank.require_no_active_flash_loan()?;
+    debt_bank.require_no_active_flash_loan()?;
     
     // Update interest rates for both banks
     collateral_bank.update_interest()?;
     debt_bank.update_interest()?;
     
-    // Get price feeds with error mapping
-    let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)
-        .map_err(|_| Lendingerror::OracleError)?;
-    let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)
-        .map_err(|_| Lendingerror::OracleError)?;
-        
-    let sol_price = price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)
-        .map_err(|_| Lendingerror::OracleError)?;
-    let usdc_price = price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)
-        .map_err(|_| Lendingerror::OracleError)?;
-    
+    // Each bank is priced from its own feed, refreshed here when a price account is passed and
+    // otherwise cached by update_price, falling back to the secondary source when stale
+    refresh_prices(ctx.remaining_accounts, &mut [collateral_bank, debt_bank])?;
     let current_timestamp = Clock::get()?.unix_timestamp;
+    let collateral_oracle = collateral_bank.oracle_price(current_timestamp)?;
+    let debt_oracle = debt_bank.oracle_price(current_timestamp)?;
     
-    // ... [Rest of your liquidation logic logic remains the same] ...
-    
-    // For brevity, copy the rest of your logic here exactly as it was. 
-    // Just make sure the "LendingError" enum usage matches what you defined in error.rs
-    
-    // Calculate current user balances using shares
-    let user_sol_deposits = if collateral_bank.total_deposits_shares > 0 {
-        user.deposited_sol_shares
-            .checked_mul(collateral_bank.total_deposits)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(collateral_bank.total_deposits_shares)
-            .ok_or(Lendingerror::MathOverflow)?
-    } else {
-        0
-    };
-    
-    let user_usdc_deposits = if collateral_bank.total_deposits_shares > 0 {
-        user.deposited_usdc_shares
-            .checked_mul(collateral_bank.total_deposits)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(collateral_bank.total_deposits_shares)
-            .ok_or(Lendingerror::MathOverflow)?
-    } else {
-        0
-    };
-    
-    let user_sol_borrowed = if debt_bank.total_borrowed_shares > 0 {
-        user.borrowed_sol_shares
-            .checked_mul(debt_bank.total_borrowed)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(debt_bank.total_borrowed_shares)
-            .ok_or(Lendingerror::MathOverflow)?
+    // Balances of the liquidated pair: debt rounds up and collateral down, against the user
+    let is_usdc_collateral = ctx.accounts.collateral_mint.key() == user.usdc_address;
+    let (collateral_shares, debt_shares) = if is_usdc_collateral {
+        (user.deposited_usdc_shares, user.borrowed_sol_shares)
     } else {
-        0
+        (user.deposited_sol_shares, user.borrowed_usdc_shares)
     };
-    
-    let user_usdc_borrowed = if debt_bank.total_borrowed_shares > 0 {
-        user.borrowed_usdc_shares
-            .checked_mul(debt_bank.total_borrowed)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(debt_bank.total_borrowed_shares)
-            .ok_or(Lendingerror::MathOverflow)?
-    } else {
-        0
-    };
-    
-    // Calculate total collateral and debt values
-    let (total_collateral, total_borrowed, is_usdc_collateral) = 
-        match ctx.accounts.collateral_mint.to_account_info().key() {
-            key if key == user.usdc_address => {
-                // USDC is collateral, SOL is debt
-                let collateral_value = (usdc_price.price as u64)
-                    .checked_mul(user_usdc_deposits)
-                    .ok_or(Lendingerror::MathOverflow)?;
-                let debt_value = (sol_price.price as u64)
-                    .checked_mul(user_sol_borrowed)
-                    .ok_or(Lendingerror::MathOverflow)?;
-                (collateral_value, debt_value, true)
-            }
-            _ => {
-                // SOL is collateral, USDC is debt
-                let collateral_value = (sol_price.price as u64)
-                    .checked_mul(user_sol_deposits)
-                    .ok_or(Lendingerror::MathOverflow)?;
-                let debt_value = (usdc_price.price as u64)
-                    .checked_mul(user_usdc_borrowed)
-                    .ok_or(Lendingerror::MathOverflow)?;
-                (collateral_value, debt_value, false)
-            }
-        };
-    
-    // Calculate health factor
-    let health_factor = if total_borrowed > 0 {
-        (total_collateral as f64 * collateral_bank.liquidation_threshold as f64) 
-            / (total_borrowed as f64 * 100.0)
+    let user_collateral = shares_to_amount(collateral_shares, collateral_bank.total_deposits, collateral_bank.total_deposits_shares, Rounding::Down)
+        .ok_or(LendingError::MathOverflow)?;
+    let user_debt = shares_to_amount(debt_shares, debt_bank.total_borrowed, debt_bank.total_borrowed_shares, Rounding::Up)
+        .ok_or(LendingError::MathOverflow)?;
+    
+    // Check if liquidation is allowed, valuing everything the user holds in both banks
+    let position = if is_usdc_collateral {
+        position_value(user, collateral_bank, collateral_oracle, debt_bank, debt_oracle)?
     } else {
-        f64::INFINITY
+        position_value(user, debt_bank, debt_oracle, collateral_bank, collateral_oracle)?
     };
-    
-    // Check if liquidation is allowed
-    if health_factor >= 1.0 {
-        return Err(Lendingerror::HealthFactorAboveOne.into());
+    let health_factor = health_factor(position.liquidation_collateral, position.debt);
+    if health_factor >= Ratio::ONE {
+        return Err(LendingError::HealthFactorAboveOne.into());
     }
     
-    // Calculate liquidation amount (debt to be repaid)
-    let liquidation_amt = total_borrowed
-        .checked_mul(debt_bank.liquidation_close_factor)
-        .ok_or(Lendingerror::MathOverflow)?
-        .checked_div(100)
-        .ok_or(Lendingerror::MathOverflow)?;
-    
-    // Calculate liquidator reward (collateral amount with bonus)
-    let liquidator_reward = liquidation_amt
-        .checked_mul(collateral_bank.liquidation_bonus)
-        .ok_or(Lendingerror::MathOverflow)?
-        .checked_div(100)
-        .ok_or(Lendingerror::MathOverflow)?
-        .checked_add(liquidation_amt)
-        .ok_or(Lendingerror::MathOverflow)?;
+    // Debt tokens to repay and collateral tokens paid out for them, bonus included
+    let (liquidation_amt, liquidator_reward) = liquidation_amounts(
+        debt_bank,
+        user_debt,
+        debt_oracle,
+        collateral_bank,
+        user_collateral,
+        collateral_oracle,
+    )?;
     
     // Transfer debt tokens from liquidator to debt bank
     let transfer_to_bank = TransferChecked {
@@ -223,44 +156,30 @@ pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
     
     token_interface::transfer_checked(cpi_ctx_to_liquidator, liquidator_reward, collateral_decimals)?;
     
-    // Calculate shares to be reduced
-    let debt_shares_to_reduce = if debt_bank.total_borrowed > 0 {
-        liquidation_amt
-            .checked_mul(debt_bank.total_borrowed_shares)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(debt_bank.total_borrowed)
-            .ok_or(Lendingerror::MathOverflow)?
-    } else {
-        0
-    };
+    // Calculate shares to be reduced, rounding in the protocol's favor
+    let debt_shares_to_reduce = amount_to_shares(liquidation_amt, debt_bank.total_borrowed, debt_bank.total_borrowed_shares, Rounding::Down)
+        .ok_or(LendingError::MathOverflow)?;
     
-    let collateral_shares_to_reduce = if collateral_bank.total_deposits > 0 {
-        liquidator_reward
-            .checked_mul(collateral_bank.total_deposits_shares)
-            .ok_or(Lendingerror::MathOverflow)?
-            .checked_div(collateral_bank.total_deposits)
-            .ok_or(Lendingerror::MathOverflow)?
-    } else {
-        0
-    };
+    let collateral_shares_to_reduce = amount_to_shares(liquidator_reward, collateral_bank.total_deposits, collateral_bank.total_deposits_shares, Rounding::Up)
+        .ok_or(LendingError::MathOverflow)?;
     
     // Update user account shares
     if is_usdc_collateral {
         // USDC collateral, SOL debt
         user.deposited_usdc_shares = user.deposited_usdc_shares
             .checked_sub(collateral_shares_to_reduce)
-            .ok_or(Lendingerror::InsufficientBalance)?;
+            .ok_or(LendingError::InsufficientBalance)?;
         user.borrowed_sol_shares = user.borrowed_sol_shares
             .checked_sub(debt_shares_to_reduce)
-            .ok_or(Lendingerror::InsufficientBalance)?;
+            .ok_or(LendingError::InsufficientBalance)?;
     } else {
         // SOL collateral, USDC debt
         user.deposited_sol_shares = user.deposited_sol_shares
             .checked_sub(collateral_shares_to_reduce)
-            .ok_or(Lendingerror::InsufficientBalance)?;
+            .ok_or(LendingError::InsufficientBalance)?;
         user.borrowed_usdc_shares = user.borrowed_usdc_shares
             .checked_sub(debt_shares_to_reduce)
-            .ok_or(Lendingerror::InsufficientBalance)?;
+            .ok_or(LendingError::InsufficientBalance)?;
     }
     
     // Update timestamps
@@ -270,23 +189,43 @@ pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
     // Update bank totals
     collateral_bank.total_deposits = collateral_bank.total_deposits
         .checked_sub(liquidator_reward)
-        .ok_or(Lendingerror::InsufficientBalance)?;
+        .ok_or(LendingError::InsufficientBalance)?;
     
     collateral_bank.total_deposits_shares = collateral_bank.total_deposits_shares
         .checked_sub(collateral_shares_to_reduce)
-        .ok_or(Lendingerror::InsufficientBalance)?;
+        .ok_or(LendingError::InsufficientBalance)?;
     
+    debt_bank.collect_origination_fees(liquidation_amt)?;
     debt_bank.total_borrowed = debt_bank.total_borrowed
         .checked_sub(liquidation_amt)
-        .ok_or(Lendingerror::InsufficientBalance)?;
+        .ok_or(LendingError::InsufficientBalance)?;
     
     debt_bank.total_borrowed_shares = debt_bank.total_borrowed_shares
         .checked_sub(debt_shares_to_reduce)
-        .ok_or(Lendingerror::InsufficientBalance)?;
+        .ok_or(LendingError::InsufficientBalance)?;
     
     // Update interest rates after liquidation
     collateral_bank.update_interest()?;
     debt_bank.update_interest()?;
+
+    emit_cpi!(Liquidated {
+        liquidator: ctx.accounts.liquidator.key(),
+        user: user.owner,
+        collateral_bank: collateral_bank.key(),
+        debt_bank: debt_bank.key(),
+        debt_repaid: liquidation_amt,
+        debt_shares_burned: debt_shares_to_reduce,
+        collateral_seized: liquidator_reward,
+        collateral_shares_burned: collateral_shares_to_reduce,
+        collateral_price: collateral_oracle.price,
+        collateral_price_exponent: collateral_oracle.exponent,
+        debt_price: debt_oracle.price,
+        debt_price_exponent: debt_oracle.exponent,
+        collateral_price_source: collateral_oracle.source,
+        debt_price_source: debt_oracle.source,
+        health_factor: health_factor.0,
+        timestamp: current_timestamp,
+    });
     
     Ok(())
 }
\ No newline at end of file
@@ -20,4 +20,6 @@ pub mod update_interest;
 pub use update_price::*;
 pub mod update_price;
 pub use governance::*;
-pub mod governance;
\ No newline at end of file
+pub mod governance;
+pub use vote_escrow::*;
+pub mod vote_escrow;
\ No newline at end of file
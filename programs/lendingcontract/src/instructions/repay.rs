@@ -2,7 +2,8 @@
 
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked}};
-use crate::{error::Lendingerror, state::*};
+use crate::{constants::FULL_BALANCE, error::LendingError, events::Repaid, state::*};
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Repay<'info>{
     #[account(mut)]
@@ -26,6 +27,7 @@ pub bank_token_account:InterfaceAccount<'info,TokenAccount>,
     mut,
     seeds=[signer.key().as_ref()],
     bump,
+    constraint = user_account.other_mint(mint.key()).is_some() @ LendingError::PositionMintMismatch,
 )]
 pub user_account:Account<'info,User>,
 #[account(
@@ -47,24 +49,32 @@ pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
     
+    bank.require_no_active_flash_loan()?;
+    
     // Update interest rates before repaying
     bank.update_interest()?;
     
-    let borrowed_val: u64;
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            borrowed_val = user.borrowed_usdc;
-        }
-        _ => {
-            borrowed_val = user.borrowed_sol;
-        }
-    }
-    
-    let accrued_borrow = calculate_accrued_interest(borrowed_val, bank.current_borrow_rate, user.last_updated_borrow)?;
+    let is_usdc = ctx.accounts.mint.to_account_info().key() == user.usdc_address;
+    let user_debt_shares = if is_usdc { user.borrowed_usdc_shares } else { user.borrowed_sol_shares };
     
-    if amount > accrued_borrow {
-        return Err(Lendingerror::OverRepay.into());
-    }
+    let (amount, user_shares) = if amount == FULL_BALANCE {
+        // Clear every debt share, charging what they are worth rounded up
+        let full_amount = shares_to_amount(user_debt_shares, bank.total_borrowed, bank.total_borrowed_shares, Rounding::Up)
+            .ok_or(LendingError::MathOverflow)?
+            .min(bank.total_borrowed);
+        (full_amount, user_debt_shares)
+    } else {
+        // Capped at what the user's debt shares are worth, accrued interest included
+        let borrowed_val = shares_to_amount(user_debt_shares, bank.total_borrowed, bank.total_borrowed_shares, Rounding::Up)
+            .ok_or(LendingError::MathOverflow)?;
+        require!(amount <= borrowed_val, LendingError::OverRepay);
+        
+        // Debt shares are burned rounding down, never more than the user holds
+        let user_shares = amount_to_shares(amount, bank.total_borrowed, bank.total_borrowed_shares, Rounding::Down)
+            .ok_or(LendingError::MathOverflow)?
+            .min(user_debt_shares);
+        (amount, user_shares)
+    };
     
     let transfer_cpi_acc = TransferChecked {
         from: ctx.accounts.user_token_account.to_account_info(),
@@ -78,22 +88,35 @@ pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
     let decimals: u8 = ctx.accounts.mint.decimals;
     token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
     
-    let borrowed_ratio: u64 = amount.checked_div(bank.total_borrowed).unwrap();
-    let user_shares = bank.total_borrowed_shares.checked_mul(borrowed_ratio).unwrap();
-    
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc -= amount;
-            user.borrowed_usdc_shares -= user_shares;
-        }
-        _ => {
-            user.borrowed_sol -= amount;
-            user.borrowed_sol_shares -= user_shares;
-        }
+    // The principal fields don't track accrued interest, so repaying interest can take them to zero
+    if is_usdc {
+        user.borrowed_usdc_shares -= user_shares;
+        user.borrowed_usdc = if user.borrowed_usdc_shares == 0 { 0 } else { user.borrowed_usdc.saturating_sub(amount) };
+    } else {
+        user.borrowed_sol_shares -= user_shares;
+        user.borrowed_sol = if user.borrowed_sol_shares == 0 { 0 } else { user.borrowed_sol.saturating_sub(amount) };
     }
     
-    bank.total_borrowed -= amount;
-    bank.total_borrowed_shares -= user_shares;
+    bank.collect_origination_fees(amount)?;
+    bank.total_borrowed = bank.total_borrowed
+        .checked_sub(amount)
+        .ok_or(LendingError::InsufficientBalance)?;
+    bank.total_borrowed_shares = bank.total_borrowed_shares
+        .checked_sub(user_shares)
+        .ok_or(LendingError::InsufficientBalance)?;
+
+    emit_cpi!(Repaid {
+        user: user.owner,
+        bank: bank.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: user_shares,
+        total_borrowed: bank.total_borrowed,
+        total_borrowed_shares: bank.total_borrowed_shares,
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     
     Ok(())
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
-use crate::state::*;
+use crate::{events::InterestAccrued, state::*};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdateInterest<'info> {
     #[account(mut)]
@@ -10,5 +11,14 @@ pub struct UpdateInterest<'info> {
 pub fn update_interest(ctx: Context<UpdateInterest>) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     bank.update_interest()?;
+
+    emit_cpi!(InterestAccrued {
+        bank: bank.key(),
+        total_deposits: bank.total_deposits,
+        total_borrowed: bank.total_borrowed,
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        timestamp: bank.last_updated,
+    });
     Ok(())
 }
\ No newline at end of file
@@ -1,29 +1,154 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
 use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::{error::LendingError, events::{CircuitBreakerTripped, PriceUpdated}, state::*};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdatePrice<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    // The Pyth PriceUpdateV2 account. 
-    // We don't mark it #[account(mut)] because we aren't writing to it directly; 
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    // The Pyth PriceUpdateV2 account.
+    // We don't mark it #[account(mut)] because we aren't writing to it directly;
     // the Pyth program writes to it.
     pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Update for the bank's secondary feed, when it falls back to one
+    pub secondary_price_update: Option<Account<'info, PriceUpdateV2>>,
+
+    /// CHECK: the bank's configured SPL stake pool, required in LST mode. Key and owner are
+    /// checked and the exchange rate parsed in `read_stake_pool`.
+    pub stake_pool: Option<UncheckedAccount<'info>>,
+}
+
+fn read_stake_pool(bank: &Bank, stake_pool: Option<&UncheckedAccount>) -> Result<StakePoolRate> {
+    let stake_pool = stake_pool.ok_or(LendingError::InvalidStakePool)?;
+    require_keys_eq!(stake_pool.key(), bank.stake_pool, LendingError::InvalidStakePool);
+    require_keys_eq!(*stake_pool.owner, STAKE_POOL_PROGRAM_ID, LendingError::InvalidStakePool);
+    StakePoolRate::try_from_account_data(&stake_pool.try_borrow_data()?)
+}
+
+/// Event for a breaker that `record_price` has just tripped on `bank`.
+fn circuit_breaker_tripped(bank: &Account<Bank>) -> CircuitBreakerTripped {
+    msg!("Price moved from {} to {}; circuit breaker tripped", bank.circuit_breaker_reference_price, bank.price);
+    CircuitBreakerTripped {
+        bank: bank.key(),
+        reference_price: bank.circuit_breaker_reference_price,
+        price: bank.price,
+        exponent: bank.price_exponent,
+        tripped_at: bank.circuit_breaker_tripped_at,
+    }
+}
+
+/// Refreshes each bank's cached price from the PriceUpdateV2 account at the same position in
+/// `price_accounts`, which must carry that bank's feed. Banks without one keep their cached price.
+pub fn refresh_prices(price_accounts: &[AccountInfo], banks: &mut [&mut Bank]) -> Result<()> {
+    require!(price_accounts.len() <= banks.len(), LendingError::TooManyPriceAccounts);
+    let clock = Clock::get()?;
+    for (info, bank) in price_accounts.iter().zip(banks.iter_mut()) {
+        require_keys_eq!(*info.owner, PriceUpdateV2::owner(), LendingError::OracleError);
+        let price_update = PriceUpdateV2::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(bank.oracle_feed_id != [0; 32], LendingError::OracleNotConfigured);
+        // LST banks also need their stake pool, so they are refreshed through update_price
+        require!(bank.stake_pool == Pubkey::default(), LendingError::InvalidStakePool);
+        require!(
+            price_update.price_message.feed_id == bank.oracle_feed_id,
+            LendingError::OracleFeedMismatch
+        );
+        let price = price_update.get_price_no_older_than(&clock, bank.max_price_age, &bank.oracle_feed_id)
+            .map_err(|_| LendingError::StalePrice)?;
+        // Another transaction may have cached a newer price already
+        if price.publish_time > bank.price_publish_time {
+            bank.record_price(price.price, price.conf, price.exponent, price.publish_time)?;
+        }
+    }
+    Ok(())
 }
 
 pub fn update_price(ctx: Context<UpdatePrice>) -> Result<()> {
     // In the Pyth Pull Oracle model, the client (frontend/ts-script) sends two instructions:
     // 1. PythProgram.postUpdate(...) -> Writes new price to price_update account
     // 2. LendingProgram.updatePrice(...) -> This instruction
-    
-    // Since the data is already updated by the time this runs, we mostly use this 
-    // for logging or optional verification.
-    
-    msg!("Price update account verified: {}", ctx.accounts.price_update.key());
-    
-    // Note: Your borrow/liquidate instructions will automatically read 
-    // the fresh price from this account using 'get_price_no_older_than'.
-    
+    //
+    // This instruction checks the update is for the bank's feed and fresh, then caches it
+    // on the bank where borrow and liquidate read it. A stale primary is tolerated when a
+    // fresh secondary update comes along, so the bank can fail over to it.
+    //
+    // In LST mode the primary feed is SOL/USD and the cached price is derived from it through
+    // the stake pool's exchange rate.
+    let bank = &mut ctx.accounts.bank;
+    let price_update = &ctx.accounts.price_update;
+    let clock = Clock::get()?;
+    require!(bank.oracle_feed_id != [0; 32], LendingError::OracleNotConfigured);
+    require!(
+        price_update.price_message.feed_id == bank.oracle_feed_id,
+        LendingError::OracleFeedMismatch
+    );
+
+    let mut secondary_recorded = false;
+    if let Some(secondary_update) = &ctx.accounts.secondary_price_update {
+        require!(
+            bank.secondary_oracle == SecondaryOracle::PythFeed,
+            LendingError::InvalidOracleConfig
+        );
+        require!(
+            secondary_update.price_message.feed_id == bank.secondary_feed_id,
+            LendingError::OracleFeedMismatch
+        );
+        if let Ok(price) = secondary_update.get_price_no_older_than(&clock, bank.max_price_age, &bank.secondary_feed_id) {
+            bank.record_secondary_price(price.price, price.exponent, price.publish_time)?;
+            secondary_recorded = true;
+        }
+    }
+
+    let tripped_at = bank.circuit_breaker_tripped_at;
+    let mut stake_pool_rate = Bps::ZERO;
+    match price_update.get_price_no_older_than(&clock, bank.max_price_age, &bank.oracle_feed_id) {
+        Ok(price) if bank.stake_pool != Pubkey::default() => {
+            let pool = read_stake_pool(bank, ctx.accounts.stake_pool.as_ref())?;
+            let lst_price = bank.stake_pool_price(price.price, &pool, clock.epoch)?;
+            let lst_conf = u64::try_from(price.conf as u128 * pool.total_lamports as u128 / pool.pool_token_supply as u128)
+                .map_err(|_| LendingError::MathOverflow)?;
+            stake_pool_rate = pool.rate().ok_or(LendingError::InvalidStakePool)?;
+            bank.record_price(lst_price, lst_conf, price.exponent, price.publish_time)?;
+        }
+        Ok(price) => bank.record_price(price.price, price.conf, price.exponent, price.publish_time)?,
+        Err(_) => {
+            require!(secondary_recorded, LendingError::StalePrice);
+            msg!("Primary price is stale; recorded the secondary feed only");
+        }
+    }
+
+    if bank.price_jump_flagged {
+        msg!("Price {} deviates from TWAP {} beyond the configured limit", bank.price, bank.twap_price);
+    }
+
+    if bank.circuit_breaker_tripped_at != tripped_at {
+        emit_cpi!(circuit_breaker_tripped(bank));
+    }
+
+    emit_cpi!(PriceUpdated {
+        bank: bank.key(),
+        price: bank.price,
+        conf: bank.price_conf,
+        exponent: bank.price_exponent,
+        publish_time: bank.price_publish_time,
+        twap_price: bank.twap_price,
+        jump_flagged: bank.price_jump_flagged,
+        secondary_price: if secondary_recorded { bank.secondary_price } else { 0 },
+        secondary_publish_time: if secondary_recorded { bank.secondary_price_publish_time } else { 0 },
+        stake_pool_rate: stake_pool_rate.0,
+    });
+
     Ok(())
-}
\ No newline at end of file
+}
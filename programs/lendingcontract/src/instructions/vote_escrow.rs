@@ -0,0 +1,150 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked};
+use crate::{constants::MAX_LOCK_DURATION, error::LendingError, events::*, state::*};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct LockGovernanceTokens<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + VoteLock::INIT_SPACE,
+        seeds = [b"vote_lock", governance_mint.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub vote_lock: Account<'info, VoteLock>,
+
+    #[account(
+        init,
+        payer = owner,
+        token::mint = governance_mint,
+        token::authority = lock_vault,
+        token::token_program = token_program,
+        seeds = [b"vote_lock_vault", vote_lock.key().as_ref()],
+        bump,
+    )]
+    pub lock_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = governance_mint,
+        token::authority = owner,
+        token::token_program = token_program,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UnlockGovernanceTokens<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"vote_lock", governance_mint.key().as_ref(), owner.key().as_ref()],
+        bump,
+        has_one = owner,
+        close = owner,
+    )]
+    pub vote_lock: Account<'info, VoteLock>,
+
+    #[account(
+        mut,
+        seeds = [b"vote_lock_vault", vote_lock.key().as_ref()],
+        bump,
+    )]
+    pub lock_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = governance_mint,
+        token::authority = owner,
+        token::token_program = token_program,
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn lock_governance_tokens(ctx: Context<LockGovernanceTokens>, amount: u64, duration: i64) -> Result<()> {
+    require!(amount > 0, LendingError::InvalidLockAmount);
+    require!(duration > 0 && duration <= MAX_LOCK_DURATION, LendingError::InvalidLockDuration);
+
+    let transfer_cpi_acc = TransferChecked {
+        from: ctx.accounts.owner_token_account.to_account_info(),
+        mint: ctx.accounts.governance_mint.to_account_info(),
+        to: ctx.accounts.lock_vault.to_account_info(),
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_cpi_acc);
+    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.governance_mint.decimals)?;
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let vote_lock = &mut ctx.accounts.vote_lock;
+    vote_lock.owner = ctx.accounts.owner.key();
+    vote_lock.mint = ctx.accounts.governance_mint.key();
+    vote_lock.amount = amount;
+    vote_lock.lock_start = current_time;
+    vote_lock.lock_end = current_time + duration;
+
+    msg!("Locked {} governance tokens until {}", amount, vote_lock.lock_end);
+
+    emit_cpi!(GovernanceTokensLocked {
+        vote_lock: vote_lock.key(),
+        owner: vote_lock.owner,
+        mint: vote_lock.mint,
+        amount,
+        lock_end: vote_lock.lock_end,
+    });
+    Ok(())
+}
+
+pub fn unlock_governance_tokens(ctx: Context<UnlockGovernanceTokens>) -> Result<()> {
+    let vote_lock = &ctx.accounts.vote_lock;
+    require!(Clock::get()?.unix_timestamp >= vote_lock.lock_end, LendingError::LockNotExpired);
+
+    let vote_lock_key = vote_lock.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"vote_lock_vault",
+        vote_lock_key.as_ref(),
+        &[ctx.bumps.lock_vault],
+    ]];
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+
+    let transfer_cpi_acc = TransferChecked {
+        from: ctx.accounts.lock_vault.to_account_info(),
+        mint: ctx.accounts.governance_mint.to_account_info(),
+        to: ctx.accounts.owner_token_account.to_account_info(),
+        authority: ctx.accounts.lock_vault.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(cpi_program.clone(), transfer_cpi_acc).with_signer(signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, vote_lock.amount, ctx.accounts.governance_mint.decimals)?;
+
+    let close_cpi_acc = CloseAccount {
+        account: ctx.accounts.lock_vault.to_account_info(),
+        destination: ctx.accounts.owner.to_account_info(),
+        authority: ctx.accounts.lock_vault.to_account_info(),
+    };
+    token_interface::close_account(CpiContext::new(cpi_program, close_cpi_acc).with_signer(signer_seeds))?;
+
+    msg!("Unlocked {} governance tokens", vote_lock.amount);
+
+    emit_cpi!(GovernanceTokensUnlocked {
+        vote_lock: vote_lock_key,
+        owner: vote_lock.owner,
+        mint: vote_lock.mint,
+        amount: vote_lock.amount,
+    });
+    Ok(())
+}
@@ -1,8 +1,9 @@
 
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked}};
-use crate::{error::Lendingerror, state::*};
+use crate::{constants::FULL_BALANCE, error::LendingError, events::Withdrawn, instructions::refresh_prices, state::*};
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Withdraw<'info>{
 #[account(mut)]
@@ -36,32 +37,76 @@ pub user_account:Account<'info,User>,
 pub user_token_account:InterfaceAccount<'info,TokenAccount>,
 pub token_program:Interface<'info,TokenInterface>,
 pub associated_token_program:Program<'info,AssociatedToken>,
-pub system_program:Program<'info,System>
+pub system_program:Program<'info,System>,
+// Bank of the user's other asset; together with `bank` it values the whole position
+#[account(
+    mut,
+    seeds=[other_bank.mint_address.as_ref()],
+    bump,
+    constraint = other_bank.key() != bank.key(),
+    constraint = user_account.other_mint(mint.key()) == Some(other_bank.mint_address) @ LendingError::PositionMintMismatch,
+)]
+pub other_bank:Account<'info,Bank>,
 }
 
 
+// remaining_accounts: optional PriceUpdateV2 for [bank, other_bank], each on that bank's feed
 pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
+    let other_bank = &mut ctx.accounts.other_bank;
     let user = &mut ctx.accounts.user_account;
     
+    bank.require_no_active_flash_loan()?;
+    // A price passed in here that trips a breaker fails the withdrawal and is rolled back with
+    // it; update_price is the crank that records the trip
+    refresh_prices(ctx.remaining_accounts, &mut [bank, other_bank])?;
+    let now = Clock::get()?.unix_timestamp;
+    bank.require_circuit_breaker_closed(now)?;
+    
     // Update interest rates before withdrawing
     bank.update_interest()?;
     
-    let deposited_val: u64;
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        deposited_val = user.deposited_usdc;
-    } else {
-        deposited_val = user.deposited_sol;
-    }
-    
-    if amount > deposited_val {
-        return Err(Lendingerror::InsufficientFunds.into());
-    }
+    let is_usdc = ctx.accounts.mint.to_account_info().key() == user.usdc_address;
+    let user_deposit_shares = if is_usdc { user.deposited_usdc_shares } else { user.deposited_sol_shares };
     
-    let accrued_deposit = calculate_accrued_interest(deposited_val, bank.current_supply_rate, user.last_updated)?;
+    let (amount, shares_to_remove) = if amount == FULL_BALANCE {
+        // Redeem every deposit share at what it is worth, rounded down
+        let full_amount = shares_to_amount(user_deposit_shares, bank.total_deposits, bank.total_deposits_shares, Rounding::Down)
+            .ok_or(LendingError::MathOverflow)?
+            .min(bank.total_deposits);
+        (full_amount, user_deposit_shares)
+    } else {
+        // Capped at what the user's shares are worth, so accrued interest can be withdrawn
+        let deposited_val = shares_to_amount(user_deposit_shares, bank.total_deposits, bank.total_deposits_shares, Rounding::Down)
+            .ok_or(LendingError::MathOverflow)?;
+        require!(amount <= deposited_val, LendingError::InsufficientFunds);
+        
+        // Deposit shares are burned rounding up, so withdrawals never dilute other depositors
+        let shares_to_remove = amount_to_shares(amount, bank.total_deposits, bank.total_deposits_shares, Rounding::Up)
+            .ok_or(LendingError::MathOverflow)?;
+        (amount, shares_to_remove)
+    };
     
-    if amount as f64 > accrued_deposit as f64 {
-        return Err(Lendingerror::InsufficientFunds.into());
+    require!(
+        amount <= bank.available_liquidity(ctx.accounts.bank_token_account.amount),
+        LendingError::InsufficientLiquidity
+    );
+
+    // With open borrows, what remains after the withdrawal must still cover them at max LTV
+    let mut prices = None;
+    if user.borrowed_sol_shares > 0 || user.borrowed_usdc_shares > 0 {
+        // The other bank's price values the rest of the position, so its breaker must be closed too
+        other_bank.require_circuit_breaker_closed(now)?;
+        let bank_price = bank.oracle_price(now)?;
+        let other_price = other_bank.oracle_price(now)?;
+        prices = Some((bank_price, other_price));
+        let position = if is_usdc {
+            position_value(user, bank, bank_price, other_bank, other_price)?
+        } else {
+            position_value(user, other_bank, other_price, bank, bank_price)?
+        };
+        let borrow_power = position.borrow_power_after_withdraw(bank, amount, bank_price)?;
+        require!(borrow_power >= position.debt, LendingError::WithdrawExceedsBorrowPower);
     }
     
     let transfer_cpi_acc = TransferChecked {
@@ -84,18 +129,44 @@ pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
     let decimals = ctx.accounts.mint.decimals;
     token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
     
-    let shares_to_remove = (amount as f64 / bank.total_deposits as f64) * bank.total_deposits_shares as f64;
-    
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        user.deposited_usdc -= amount;
-        user.deposited_usdc_shares -= shares_to_remove as u64;
+    // The principal fields don't track accrued interest, so a full withdrawal can exceed them
+    if is_usdc {
+        user.deposited_usdc_shares = user.deposited_usdc_shares
+            .checked_sub(shares_to_remove)
+            .ok_or(LendingError::InsufficientFunds)?;
+        user.deposited_usdc = if user.deposited_usdc_shares == 0 { 0 } else { user.deposited_usdc.saturating_sub(amount) };
     } else {
-        user.deposited_sol -= amount;
-        user.deposited_sol_shares -= shares_to_remove as u64;
+        user.deposited_sol_shares = user.deposited_sol_shares
+            .checked_sub(shares_to_remove)
+            .ok_or(LendingError::InsufficientFunds)?;
+        user.deposited_sol = if user.deposited_sol_shares == 0 { 0 } else { user.deposited_sol.saturating_sub(amount) };
     }
     
-    bank.total_deposits -= amount;
-    bank.total_deposits_shares -= shares_to_remove as u64;
+    bank.total_deposits = bank.total_deposits
+        .checked_sub(amount)
+        .ok_or(LendingError::InsufficientFunds)?;
+    bank.total_deposits_shares = bank.total_deposits_shares
+        .checked_sub(shares_to_remove)
+        .ok_or(LendingError::InsufficientFunds)?;
+
+    emit_cpi!(Withdrawn {
+        user: user.owner,
+        bank: bank.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: shares_to_remove,
+        total_deposits: bank.total_deposits,
+        total_deposits_shares: bank.total_deposits_shares,
+        borrow_rate: bank.current_borrow_rate,
+        supply_rate: bank.current_supply_rate,
+        price: prices.map_or(0, |(price, _)| price.price),
+        price_exponent: prices.map_or(0, |(price, _)| price.exponent),
+        price_source: prices.map(|(price, _)| price.source),
+        other_price: prices.map_or(0, |(_, other)| other.price),
+        other_price_exponent: prices.map_or(0, |(_, other)| other.exponent),
+        other_price_source: prices.map(|(_, other)| other.source),
+        timestamp: now,
+    });
     
     Ok(())
 }
\ No newline at end of file
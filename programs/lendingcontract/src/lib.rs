@@ -1,10 +1,12 @@
 use anchor_lang::prelude::*;
 use instructions::*;
+use state::SecondaryOracle;
 
 mod state;
 mod instructions;
 mod error;
 mod constants;
+mod events;
 
 declare_id!("A9ALyfnt8LrVCz2uvhHnqHQFA3k5dUq7dAJxXo1Dikdy");
 
@@ -12,6 +14,7 @@ declare_id!("A9ALyfnt8LrVCz2uvhHnqHQFA3k5dUq7dAJxXo1Dikdy");
 pub mod lending {
    use super::*;
 
+   #[allow(clippy::too_many_arguments)]
    pub fn initialize_bank(
         ctx: Context<InitializeBank>,
         liquidation_threshold: u64,
@@ -34,12 +37,46 @@ pub mod lending {
         )
     }
 
+    pub fn initialize_roles(ctx: Context<InitializeRoles>) -> Result<()> {
+        instructions::initialize_roles(ctx)
+    }
+
+    pub fn update_roles(
+        ctx: Context<UpdateRoles>,
+        risk_admin: Option<Pubkey>,
+        rate_admin: Option<Pubkey>,
+        guardian: Option<Pubkey>,
+        fee_admin: Option<Pubkey>,
+    ) -> Result<()> {
+        instructions::update_roles(ctx, risk_admin, rate_admin, guardian, fee_admin)
+    }
+
+    pub fn transfer_admin(ctx: Context<TransferAdmin>) -> Result<()> {
+        instructions::transfer_admin(ctx)
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        instructions::accept_admin(ctx)
+    }
+
+    pub fn cancel_admin_transfer(ctx: Context<UpdateRoles>) -> Result<()> {
+        instructions::cancel_admin_transfer(ctx)
+    }
+
     pub fn transfer_authority(ctx: Context<TransferAuthority>) -> Result<()> {
         instructions::transfer_authority(ctx)
     }
 
-    pub fn initialize_user(ctx: Context<InitializeUser>, usdc_address: Pubkey) -> Result<()> {
-        instructions::inituser(ctx, usdc_address)
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        instructions::accept_authority(ctx)
+    }
+
+    pub fn cancel_authority_transfer(ctx: Context<CancelAuthorityTransfer>) -> Result<()> {
+        instructions::cancel_authority_transfer(ctx)
+    }
+
+    pub fn initialize_user(ctx: Context<InitializeUser>, usdc_address: Pubkey, sol_address: Pubkey) -> Result<()> {
+        instructions::inituser(ctx, usdc_address, sol_address)
     }
     pub fn depositmain(ctx:Context<Deposit>,amount:u64)->Result<()>{
         instructions::deposit(ctx, amount)
@@ -75,8 +112,84 @@ pub mod lending {
         liquidation_bonus: Option<u64>,
         liquidation_close_factor: Option<u64>,
         max_ltv: Option<u64>,
+        min_liquidity_buffer: Option<u64>,
+        origination_fee: Option<u64>,
+    ) -> Result<()> {
+        instructions::update_bank_config(
+            ctx,
+            liquidation_threshold,
+            liquidation_bonus,
+            liquidation_close_factor,
+            max_ltv,
+            min_liquidity_buffer,
+            origination_fee,
+        )
+    }
+    pub fn update_oracle_config(
+        ctx: Context<UpdateBankParams>,
+        oracle_feed_id: Option<[u8; 32]>,
+        max_price_age: Option<u64>,
+        max_twap_deviation: Option<u64>,
+        secondary_oracle: Option<SecondaryOracle>,
+        secondary_feed_id: Option<[u8; 32]>,
+        max_oracle_deviation: Option<u64>,
     ) -> Result<()> {
-        instructions::update_bank_config(ctx, liquidation_threshold, liquidation_bonus, liquidation_close_factor, max_ltv)
+        instructions::update_oracle_config(
+            ctx,
+            oracle_feed_id,
+            max_price_age,
+            max_twap_deviation,
+            secondary_oracle,
+            secondary_feed_id,
+            max_oracle_deviation,
+        )
+    }
+    pub fn set_stake_pool_oracle(
+        ctx: Context<UpdateBankParams>,
+        stake_pool: Pubkey,
+        min_stake_pool_rate: u64,
+        max_stake_pool_rate: u64,
+    ) -> Result<()> {
+        instructions::set_stake_pool_oracle(ctx, stake_pool, min_stake_pool_rate, max_stake_pool_rate)
+    }
+    pub fn update_circuit_breaker(
+        ctx: Context<UpdateBankParams>,
+        threshold: Option<u64>,
+        window: Option<i64>,
+        cooldown: Option<i64>,
+    ) -> Result<()> {
+        instructions::update_circuit_breaker(ctx, threshold, window, cooldown)
+    }
+    pub fn update_flash_loan_fee(
+        ctx: Context<UpdateBankParams>,
+        flash_loan_fee: Option<u64>,
+        flash_loan_protocol_share: Option<u64>,
+    ) -> Result<()> {
+        instructions::update_flash_loan_fee(ctx, flash_loan_fee, flash_loan_protocol_share)
+    }
+    pub fn add_fee_exemption(ctx: Context<AddFeeExemption>, keeper: Pubkey) -> Result<()> {
+        instructions::add_fee_exemption(ctx, keeper)
+    }
+    pub fn remove_fee_exemption(ctx: Context<RemoveFeeExemption>) -> Result<()> {
+        instructions::remove_fee_exemption(ctx)
+    }
+    pub fn set_governance_mint(ctx: Context<UpdateBankParams>, governance_mint: Pubkey) -> Result<()> {
+        instructions::set_governance_mint(ctx, governance_mint)
+    }
+    pub fn emergency_pause(ctx: Context<EmergencyControl>) -> Result<()> {
+        instructions::emergency_pause(ctx)
+    }
+    pub fn reduce_max_ltv(ctx: Context<EmergencyControl>, max_ltv: u64) -> Result<()> {
+        instructions::reduce_max_ltv(ctx, max_ltv)
+    }
+    pub fn resume_operations(ctx: Context<EmergencyControl>) -> Result<()> {
+        instructions::resume_operations(ctx)
+    }
+    pub fn reset_circuit_breaker(ctx: Context<EmergencyControl>) -> Result<()> {
+        instructions::reset_circuit_breaker(ctx)
+    }
+    pub fn withdraw_reserves(ctx: Context<WithdrawReserves>, amount: u64) -> Result<()> {
+        instructions::withdraw_reserves(ctx, amount)
     }
     pub fn update_interest(ctx: Context<UpdateInterest>) -> Result<()> {
         instructions::update_interest(ctx)
@@ -90,11 +203,26 @@ pub mod lending {
     ) -> Result<()> {
         instructions::initiate_flash_loan(ctx, amount)
     }
-    pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>) -> Result<()> {
-        instructions::repay_flash_loan(ctx)
+    pub fn repay_flash_loan(ctx: Context<RepayFlashLoan>, amount: u64) -> Result<()> {
+        instructions::repay_flash_loan(ctx, amount)
+    }
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteFlashLoan<'info>>,
+        amount: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::flash_loan(ctx, amount, data)
+    }
+    pub fn flash_loan_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteMultiFlashLoan<'info>>,
+        assets: Vec<FlashLoanAsset>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::flash_loan_multi(ctx, assets, data)
     }
 
     // Governance Instructions
+    #[allow(clippy::too_many_arguments)]
     pub fn create_proposal(
         ctx: Context<CreateProposal>, 
         proposal_id: u64, 
@@ -109,6 +237,24 @@ pub mod lending {
         instructions::cast_vote(ctx, vote_for)
     }
 
+    pub fn settle_proposal_bond(ctx: Context<SettleProposalBond>) -> Result<()> {
+        instructions::settle_proposal_bond(ctx)
+    }
+
+    pub fn submit_signed_votes<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SubmitSignedVotes<'info>>,
+    ) -> Result<()> {
+        instructions::submit_signed_votes(ctx)
+    }
+
+    pub fn lock_governance_tokens(ctx: Context<LockGovernanceTokens>, amount: u64, duration: i64) -> Result<()> {
+        instructions::lock_governance_tokens(ctx, amount, duration)
+    }
+
+    pub fn unlock_governance_tokens(ctx: Context<UnlockGovernanceTokens>) -> Result<()> {
+        instructions::unlock_governance_tokens(ctx)
+    }
+
     pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
         instructions::execute_proposal(ctx)
     }
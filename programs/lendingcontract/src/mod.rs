@@ -1,4 +1,5 @@
 pub mod state;
 pub mod error;
 pub mod instructions;
-pub mod constants; 
\ No newline at end of file
+pub mod constants;
+pub mod events;
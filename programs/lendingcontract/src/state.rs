@@ -1,34 +1,448 @@
 use anchor_lang::prelude::*;
 use std::f64::consts::E;
+use crate::{constants::MAX_LOCK_DURATION, error::LendingError};
+
+/// A fraction in basis points, where 10000 = 100%.
+/// Every risk parameter on `Bank` (LTV, liquidation threshold/bonus/close factor,
+/// kink utilization, reserve factor) uses this unit.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bps(pub u64);
+
+impl Bps {
+    pub const ZERO: Bps = Bps(0);
+    pub const ONE: Bps = Bps(BASIS_POINTS);
+
+    /// `amount * self`, rounded down.
+    pub fn apply(self, amount: u64) -> Option<u64> {
+        let value = (amount as u128) * (self.0 as u128) / (BASIS_POINTS as u128);
+        u64::try_from(value).ok()
+    }
+
+    /// `amount * self` for wide values such as position valuations, rounded down.
+    pub fn apply_u128(self, amount: u128) -> Option<u128> {
+        amount.checked_mul(self.0 as u128).map(|v| v / BASIS_POINTS as u128)
+    }
+
+    /// `1 - self`, or None if self exceeds 100%.
+    pub fn complement(self) -> Option<Bps> {
+        BASIS_POINTS.checked_sub(self.0).map(Bps)
+    }
+}
+
+/// Unsigned fixed-point ratio scaled by 1e18, where `Ratio::ONE` = 1.0.
+/// Used for derived quantities such as the health factor.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ratio(pub u128);
+
+impl Ratio {
+    pub const ONE: Ratio = Ratio(1_000_000_000_000_000_000);
+    pub const MAX: Ratio = Ratio(u128::MAX);
+
+    /// `numerator / denominator`; a zero denominator saturates to `Ratio::MAX`.
+    pub fn from_fraction(numerator: u128, denominator: u128) -> Ratio {
+        if denominator == 0 {
+            return Ratio::MAX;
+        }
+        // Whole and fractional parts apart, so values in `VALUE_DECIMALS` precision can't overflow
+        let (whole, remainder) = (numerator / denominator, numerator % denominator);
+        let fraction = match remainder.checked_mul(Self::ONE.0) {
+            Some(scaled) => scaled / denominator,
+            None => remainder / (denominator / Self::ONE.0),
+        };
+        Ratio(whole.saturating_mul(Self::ONE.0).saturating_add(fraction))
+    }
+}
+
+/// Decimals of position values: USD scaled by 10^VALUE_DECIMALS. Token amounts are normalized
+/// by their mint decimals and feed exponent, so assets with different decimals compare directly.
+pub const VALUE_DECIMALS: i32 = 18;
+
+// Power of ten taking `amount * price` (raw token units times raw price) to value units
+fn value_scale(decimals: u8, exponent: i32) -> i32 {
+    VALUE_DECIMALS + exponent - decimals as i32
+}
+
+/// Value of a raw token amount at an oracle price, in `VALUE_DECIMALS` precision, rounded down.
+pub fn token_value(amount: u64, decimals: u8, price: OraclePrice) -> Result<u128> {
+    require!(price.price > 0, LendingError::OracleError);
+    let product = (amount as u128).checked_mul(price.price as u128).ok_or(LendingError::MathOverflow)?;
+    let scale = value_scale(decimals, price.exponent);
+    let factor = 10u128.checked_pow(scale.unsigned_abs()).ok_or(LendingError::MathOverflow)?;
+    if scale >= 0 {
+        product.checked_mul(factor).ok_or_else(|| error!(LendingError::MathOverflow))
+    } else {
+        Ok(product / factor)
+    }
+}
+
+/// Rounding direction for share conversions. Each conversion rounds in the
+/// protocol's favor: users receive fewer shares and burn more of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// Virtual shares and assets added to every pool when converting between the two.
+///
+/// Without them the first depositor can mint a single share and then inflate
+/// `total_deposits` so later deposits round down to zero shares. With the offsets an
+/// empty pool still prices shares 1:1, but an inflation has to outweigh the virtual
+/// position, and most of the value donated to do so accrues to the virtual shares.
+pub const VIRTUAL_SHARES: u64 = 1_000_000;
+pub const VIRTUAL_ASSETS: u64 = 1_000_000;
+
+fn mul_div(value: u64, numerator: u128, denominator: u128, rounding: Rounding) -> Option<u64> {
+    let product = (value as u128).checked_mul(numerator)?;
+    let result = match rounding {
+        Rounding::Down => product.checked_div(denominator)?,
+        Rounding::Up => product.checked_add(denominator.checked_sub(1)?)? / denominator,
+    };
+    u64::try_from(result).ok()
+}
+
+/// Converts a token amount into pool shares at the current exchange rate, including
+/// the virtual offsets. An empty pool mints shares 1:1.
+///
+/// Deposits mint (`Down`), withdrawals burn (`Up`), borrows mint debt (`Up`) and
+/// repayments burn debt (`Down`).
+pub fn amount_to_shares(amount: u64, total_amount: u64, total_shares: u64, rounding: Rounding) -> Option<u64> {
+    mul_div(
+        amount,
+        total_shares as u128 + VIRTUAL_SHARES as u128,
+        total_amount as u128 + VIRTUAL_ASSETS as u128,
+        rounding,
+    )
+}
+
+/// Converts pool shares back into a token amount at the current exchange rate.
+/// Deposit balances round `Down`, debt balances round `Up`.
+pub fn shares_to_amount(shares: u64, total_amount: u64, total_shares: u64, rounding: Rounding) -> Option<u64> {
+    mul_div(
+        shares,
+        total_amount as u128 + VIRTUAL_ASSETS as u128,
+        total_shares as u128 + VIRTUAL_SHARES as u128,
+        rounding,
+    )
+}
+
+/// Relative distance of `price` from `reference`, in basis points.
+pub fn price_deviation(price: i64, reference: i64) -> Bps {
+    if reference <= 0 {
+        return Bps(u64::MAX);
+    }
+    let distance = (price as i128 - reference as i128).unsigned_abs();
+    let deviation = distance * BASIS_POINTS as u128 / reference as u128;
+    Bps(u64::try_from(deviation).unwrap_or(u64::MAX))
+}
+
+/// Health factor of a position: collateral value weighted by each asset's liquidation
+/// threshold, divided by debt value. Below `Ratio::ONE` the position is liquidatable.
+pub fn health_factor(weighted_collateral: u128, debt_value: u128) -> Ratio {
+    Ratio::from_fraction(weighted_collateral, debt_value)
+}
+
+/// Raw token amount worth `value` (in `VALUE_DECIMALS` precision) at an oracle price; the inverse
+/// of `token_value`.
+pub fn value_to_tokens(value: u128, decimals: u8, price: OraclePrice, rounding: Rounding) -> Result<u64> {
+    require!(price.price > 0, LendingError::OracleError);
+    let scale = value_scale(decimals, price.exponent);
+    let factor = 10u128.checked_pow(scale.unsigned_abs()).ok_or(LendingError::MathOverflow)?;
+    let (numerator, denominator) = if scale >= 0 {
+        (value, (price.price as u128).checked_mul(factor).ok_or(LendingError::MathOverflow)?)
+    } else {
+        (value.checked_mul(factor).ok_or(LendingError::MathOverflow)?, price.price as u128)
+    };
+    let amount = match rounding {
+        Rounding::Down => numerator / denominator,
+        Rounding::Up => numerator.div_ceil(denominator),
+    };
+    u64::try_from(amount).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+/// Debt tokens repaid and collateral tokens seized by one liquidation. The close factor of the
+/// user's debt is repaid and collateral worth that plus the bonus is seized, both converted
+/// through each bank's price and decimals. When the user's collateral can't cover it, all of
+/// it is seized and the repayment shrinks to match.
+pub fn liquidation_amounts(
+    debt_bank: &Bank,
+    debt: u64,
+    debt_price: OraclePrice,
+    collateral_bank: &Bank,
+    collateral: u64,
+    collateral_price: OraclePrice,
+) -> Result<(u64, u64)> {
+    let with_bonus = Bps(BASIS_POINTS.checked_add(collateral_bank.liquidation_bonus.0).ok_or(LendingError::MathOverflow)?);
+    let repaid = debt_bank.liquidation_close_factor.apply(debt).ok_or(LendingError::MathOverflow)?;
+    let repaid_value = token_value(repaid, debt_bank.mint_decimals, debt_price)?;
+    let seized_value = with_bonus.apply_u128(repaid_value).ok_or(LendingError::MathOverflow)?;
+    let seized = value_to_tokens(seized_value, collateral_bank.mint_decimals, collateral_price, Rounding::Down);
+    match seized {
+        Ok(seized) if seized <= collateral => Ok((repaid, seized)),
+        _ => {
+            let collateral_value = token_value(collateral, collateral_bank.mint_decimals, collateral_price)?;
+            let repaid_value = collateral_value
+                .checked_mul(BASIS_POINTS as u128)
+                .ok_or(LendingError::MathOverflow)?
+                / with_bonus.0 as u128;
+            let repaid = value_to_tokens(repaid_value, debt_bank.mint_decimals, debt_price, Rounding::Down)?;
+            Ok((repaid, collateral))
+        }
+    }
+}
+
+/// A user's whole position in `VALUE_DECIMALS` precision. Each deposit is weighted by its own
+/// bank's risk parameters, so borrow, withdraw and liquidate apply the same per-asset limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionValue {
+    // Deposits weighted by their bank's max LTV: the most the position may owe
+    pub borrow_power: u128,
+    // Deposits weighted by their bank's liquidation threshold
+    pub liquidation_collateral: u128,
+    pub debt: u128,
+}
+
+impl PositionValue {
+    /// Borrow power left after withdrawing `amount` from `bank`. The withdrawn tokens only ever
+    /// counted at their own bank's max LTV, so that is all the withdrawal takes away.
+    pub fn borrow_power_after_withdraw(&self, bank: &Bank, amount: u64, price: OraclePrice) -> Result<u128> {
+        let withdrawn_power = bank.max_ltv.apply_u128(token_value(amount, bank.mint_decimals, price)?)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(self.borrow_power.saturating_sub(withdrawn_power))
+    }
+}
+
+/// Values a user's position across the USDC and SOL banks, each asset at its own bank's price
+/// and decimals. Deposits round down and debts round up.
+pub fn position_value(
+    user: &User,
+    usdc_bank: &Bank,
+    usdc_price: OraclePrice,
+    sol_bank: &Bank,
+    sol_price: OraclePrice,
+) -> Result<PositionValue> {
+    let deposits = |shares, bank: &Bank| {
+        shares_to_amount(shares, bank.total_deposits, bank.total_deposits_shares, Rounding::Down)
+            .ok_or(LendingError::MathOverflow)
+    };
+    let debts = |shares, bank: &Bank| {
+        shares_to_amount(shares, bank.total_borrowed, bank.total_borrowed_shares, Rounding::Up)
+            .ok_or(LendingError::MathOverflow)
+    };
+    let usdc_value = |amount| token_value(amount, usdc_bank.mint_decimals, usdc_price);
+    let sol_value = |amount| token_value(amount, sol_bank.mint_decimals, sol_price);
+    let usdc_collateral = usdc_value(deposits(user.deposited_usdc_shares, usdc_bank)?)?;
+    let sol_collateral = sol_value(deposits(user.deposited_sol_shares, sol_bank)?)?;
+    let weighted = |usdc_weight: Bps, sol_weight: Bps| {
+        usdc_weight.apply_u128(usdc_collateral)
+            .zip(sol_weight.apply_u128(sol_collateral))
+            .and_then(|(usdc, sol)| usdc.checked_add(sol))
+            .ok_or(LendingError::MathOverflow)
+    };
+    let debt = usdc_value(debts(user.borrowed_usdc_shares, usdc_bank)?)?
+        .checked_add(sol_value(debts(user.borrowed_sol_shares, sol_bank)?)?)
+        .ok_or(LendingError::MathOverflow)?;
+    Ok(PositionValue {
+        borrow_power: weighted(usdc_bank.max_ltv, sol_bank.max_ltv)?,
+        liquidation_collateral: weighted(usdc_bank.liquidation_threshold, sol_bank.liquidation_threshold)?,
+        debt,
+    })
+}
 
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct Bank {
+    // Owner of record for this bank, e.g. the team or DAO that listed it. It only controls its
+    // own handover; protocol powers come from LendingRoles.
     pub authority: Pubkey,
+    // Proposed authority; takes over only once it signs accept_authority
+    pub pending_authority: Pubkey,
     pub mint_address: Pubkey,
+    pub mint_decimals: u8,
     pub total_deposits: u64,
     pub total_deposits_shares: u64,
-    pub liquidation_threshold: u64,
-    pub liquidation_bonus: u64,
-    pub liquidation_close_factor: u64,
+    pub liquidation_threshold: Bps,
+    pub liquidation_bonus: Bps,
+    pub liquidation_close_factor: Bps,
     pub total_borrowed: u64,
     pub total_borrowed_shares: u64,
-    pub max_ltv: u64,
+    pub max_ltv: Bps,
     pub usdc_address: Pubkey,
     pub last_updated: i64,
 
-    // Dynamic Interest Rate Model Parameters
+    // Dynamic Interest Rate Model Parameters (annual rates in basis points)
     pub base_rate: u64,
     pub multiplier: u64,
     pub jump_multiplier: u64,
-    pub kink_utilization: u64,
+    pub kink_utilization: Bps,
     pub current_borrow_rate: u64,
     pub current_supply_rate: u64,
-    pub reserve_factor: u64,
+    pub reserve_factor: Bps,
+
+    // Protocol-owned tokens held in the treasury (e.g. slashed proposal bonds)
+    pub protocol_reserves: u64,
+
+    // Governance Parameters
+    pub proposal_bond: u64,
+    pub min_proposer_power: u64,
+    pub quorum_votes: u64,
+    // When set, voting power comes from VoteLock accounts of this mint instead of deposit shares
+    pub governance_mint: Pubkey,
+    // Share of total deposits that borrows may not draw down, kept liquid for withdrawals
+    pub min_liquidity_buffer: Bps,
+    // One-time fee charged on each borrow, added to the debt and credited to protocol reserves
+    // as borrowers repay it
+    pub origination_fee: Bps,
+    // Origination fees still owed by borrowers, part of total_borrowed but not yet in the treasury
+    pub uncollected_origination_fees: u64,
+    // Set while a flash loan is outstanding: during a flash_loan receiver callback, or between
+    // initiate_flash_loan and repay_flash_loan. Doubles as the reentrancy guard.
+    pub flash_loan_active: bool,
+    pub flash_loan_fee: Bps,
+    // Share of each flash loan fee credited to protocol reserves; the rest goes to depositors
+    pub flash_loan_protocol_share: Bps,
+
+    // Oracle: Pyth feed for this bank's mint and the last price written by update_price
+    pub oracle_feed_id: [u8; 32],
+    // Cached prices older than this (seconds) are rejected
+    pub max_price_age: u64,
+    pub price: i64,
+    pub price_conf: u64,
+    pub price_exponent: i32,
+    pub price_publish_time: i64,
+    // Time-weighted EMA of the cached price, same exponent as `price`
+    pub twap_price: i64,
+    // Spot moves larger than this from the TWAP set `price_jump_flagged`
+    pub max_twap_deviation: Bps,
+    pub price_jump_flagged: bool,
+
+    // Fallback used when the primary feed is stale, cross-checked against it when both are fresh
+    pub secondary_oracle: SecondaryOracle,
+    pub secondary_feed_id: [u8; 32],
+    pub secondary_price: i64,
+    pub secondary_price_exponent: i32,
+    pub secondary_price_publish_time: i64,
+    // Fresh primary and secondary prices further apart than this are rejected
+    pub max_oracle_deviation: Bps,
+
+    // Circuit breaker: a price move beyond the threshold within the window halts borrows and
+    // withdrawals until the cooldown passes or the guardian resets it. A zero threshold disables it.
+    pub circuit_breaker_threshold: Bps,
+    pub circuit_breaker_window: i64,
+    pub circuit_breaker_cooldown: i64,
+    // Cached price the current window is measured from, and its publish time
+    pub circuit_breaker_reference_price: i64,
+    pub circuit_breaker_reference_time: i64,
+    // Publish time of the price that tripped the breaker; 0 when not tripped
+    pub circuit_breaker_tripped_at: i64,
+
+    // LST mode: when set, the primary feed is SOL/USD and the bank's price is that times this
+    // SPL stake pool's SOL-per-pool-token rate, which must lie within the bounds below
+    pub stake_pool: Pubkey,
+    pub min_stake_pool_rate: Bps,
+    pub max_stake_pool_rate: Bps,
+}
+
+/// Where a bank's price comes from when its primary Pyth feed is stale.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SecondaryOracle {
+    #[default]
+    None,
+    // A second Pyth feed, cached by update_price from `secondary_feed_id`
+    PythFeed,
+    // The bank's own TWAP of the primary feed
+    Twap,
 }
 
+/// Which source priced an asset; recorded in events.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceSource {
+    Primary,
+    SecondaryFeed,
+    Twap,
+}
+
+/// An oracle price read back from a `Bank`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub exponent: i32,
+    pub source: PriceSource,
+}
+
+/// Re-expresses `price * 10^from` with exponent `to`, rounding toward zero.
+fn rescale_price(price: i64, from: i32, to: i32) -> Option<i64> {
+    let factor = 10i128.checked_pow(from.abs_diff(to))?;
+    let rescaled = if from >= to {
+        (price as i128).checked_mul(factor)?
+    } else {
+        price as i128 / factor
+    };
+    i64::try_from(rescaled).ok()
+}
+
+/// SPL stake pool program, owner of the pool accounts read in LST oracle mode.
+pub const STAKE_POOL_PROGRAM_ID: Pubkey = pubkey!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+
+/// The exchange-rate fields of an SPL stake pool account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StakePoolRate {
+    // Mint of the pool's token, which must be the LST bank's own mint
+    pub pool_mint: Pubkey,
+    pub total_lamports: u64,
+    pub pool_token_supply: u64,
+    pub last_update_epoch: u64,
+}
+
+// Borsh layout of `spl_stake_pool::state::StakePool` up to the fields read here: account type,
+// manager, staker, deposit authority, withdraw bump, validator list, reserve, pool mint,
+// manager fee account, token program, then total_lamports, pool_token_supply, last_update_epoch
+const STAKE_POOL_ACCOUNT_TYPE: u8 = 1;
+const STAKE_POOL_MINT_OFFSET: usize = 1 + 32 * 3 + 1 + 32 * 2;
+const STAKE_POOL_TOTAL_LAMPORTS_OFFSET: usize = 1 + 32 * 3 + 1 + 32 * 5;
+
+impl StakePoolRate {
+    pub fn try_from_account_data(data: &[u8]) -> Result<Self> {
+        require!(data.first() == Some(&STAKE_POOL_ACCOUNT_TYPE), LendingError::InvalidStakePool);
+        let read_u64 = |index: usize| -> Result<u64> {
+            let start = STAKE_POOL_TOTAL_LAMPORTS_OFFSET + 8 * index;
+            let bytes = data.get(start..start + 8).ok_or(LendingError::InvalidStakePool)?;
+            Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let pool_mint = data
+            .get(STAKE_POOL_MINT_OFFSET..STAKE_POOL_MINT_OFFSET + 32)
+            .ok_or(LendingError::InvalidStakePool)?;
+        Ok(StakePoolRate {
+            pool_mint: Pubkey::new_from_array(pool_mint.try_into().unwrap()),
+            total_lamports: read_u64(0)?,
+            pool_token_supply: read_u64(1)?,
+            last_update_epoch: read_u64(2)?,
+        })
+    }
+
+    /// SOL per pool token in basis points, rounded down.
+    pub fn rate(&self) -> Option<Bps> {
+        let rate = (self.total_lamports as u128 * BASIS_POINTS as u128).checked_div(self.pool_token_supply as u128)?;
+        u64::try_from(rate).ok().map(Bps)
+    }
+}
+
+// Lending-market-wide role keys, one per class of admin action
 #[account]
 #[derive(InitSpace)]
+pub struct LendingRoles {
+    // Root role: assigns the others and hands itself over only through transfer_admin/accept_admin
+    pub admin: Pubkey,
+    // Proposed admin; takes over only once it signs accept_admin
+    pub pending_admin: Pubkey,
+    pub risk_admin: Pubkey,
+    pub rate_admin: Pubkey,
+    pub guardian: Pubkey,
+    pub fee_admin: Pubkey,
+}
+
+#[account]
+#[derive(InitSpace, Default)]
 pub struct User {
     pub owner: Pubkey,
     pub deposited_sol_shares: u64,
@@ -42,6 +456,21 @@ pub struct User {
     pub usdc_address: Pubkey,
     pub last_updated: i64,
     pub last_updated_borrow: i64,
+    // Mint whose bank backs the sol_* fields, fixed at initialization like usdc_address
+    pub sol_address: Pubkey,
+}
+
+impl User {
+    /// The user's other position mint given one of its two, or None for any other mint.
+    pub fn other_mint(&self, mint: Pubkey) -> Option<Pubkey> {
+        if mint == self.usdc_address {
+            Some(self.sol_address)
+        } else if mint == self.sol_address {
+            Some(self.usdc_address)
+        } else {
+            None
+        }
+    }
 }
 
 #[account]
@@ -56,6 +485,13 @@ pub struct FlashLoan {
     pub created_at: i64,
 }
 
+// Keepers listed here (protocol-owned bots) pay no flash loan fee
+#[account]
+#[derive(InitSpace)]
+pub struct FeeExemption {
+    pub keeper: Pubkey,
+}
+
 // --- Governance State ---
 #[account]
 #[derive(InitSpace)]
@@ -63,7 +499,7 @@ pub struct Proposal {
     pub proposer: Pubkey,
     pub bank: Pubkey,
     pub id: u64,
-    // 1 = Update Bank Config, 2 = Update Interest Params
+    // 1 = Update Bank Config, 2 = Update Interest Params, 3 = Update Governance Params, 4 = Update Fees
     pub proposal_type: u8, 
     // Generic slots to store the proposed values
     pub param_1: u64, 
@@ -77,6 +513,8 @@ pub struct Proposal {
     pub created_at: i64,
     pub end_time: i64,
     pub executed: bool,
+    pub bond_amount: u64,
+    pub bond_settled: bool,
 }
 
 #[account]
@@ -87,9 +525,28 @@ pub struct VoteRecord {
     pub voted: bool,
 }
 
+#[account]
+#[derive(InitSpace)]
+pub struct VoteLock {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub lock_start: i64,
+    pub lock_end: i64,
+}
+
 const BASIS_POINTS: u64 = 10000;
 const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
 
+// Risk configuration bounds
+const MAX_LIQUIDATION_BONUS: Bps = Bps(2000);
+const MAX_RATE: u64 = 100_000;
+const MAX_ORIGINATION_FEE: Bps = Bps(1000);
+const MAX_FLASH_LOAN_FEE: Bps = Bps(1000);
+
+// Horizon of the price EMA kept on each bank
+const TWAP_WINDOW: i64 = 30 * 60;
+
 impl Bank {
     pub fn get_utilization_rate(&self) -> u64 {
         if self.total_deposits == 0 {
@@ -104,11 +561,12 @@ impl Bank {
     
     pub fn calculate_borrow_rate(&self) -> u64 {
         let utilization = self.get_utilization_rate();
-        if utilization <= self.kink_utilization {
+        let kink = self.kink_utilization.0;
+        if utilization <= kink {
             self.base_rate + (utilization * self.multiplier / BASIS_POINTS)
         } else {
-            let normal_rate = self.base_rate + (self.kink_utilization * self.multiplier / BASIS_POINTS);
-            let excess_utilization = utilization - self.kink_utilization;
+            let normal_rate = self.base_rate + (kink * self.multiplier / BASIS_POINTS);
+            let excess_utilization = utilization - kink;
             let jump_rate = excess_utilization * self.jump_multiplier / BASIS_POINTS;
             normal_rate + jump_rate
         }
@@ -117,7 +575,10 @@ impl Bank {
     pub fn calculate_supply_rate(&self) -> u64 {
         let borrow_rate = self.calculate_borrow_rate();
         let utilization = self.get_utilization_rate();
-        let rate_after_reserves = borrow_rate * (BASIS_POINTS - self.reserve_factor) / BASIS_POINTS;
+        let rate_after_reserves = self.reserve_factor
+            .complement()
+            .and_then(|share| share.apply(borrow_rate))
+            .unwrap_or(0);
         rate_after_reserves * utilization / BASIS_POINTS
     }
     
@@ -141,19 +602,769 @@ impl Bank {
         Ok(())
     }
 
-    pub fn calculate_flash_loan_fee(&self, amount: u64) -> u64 {
-        const FLASH_LOAN_FEE_BASIS_POINTS: u64 = 9;
-        amount * FLASH_LOAN_FEE_BASIS_POINTS / BASIS_POINTS
+    /// Checks every risk and rate parameter against its bounds and the invariants between them.
+    /// Every path that writes bank config (init, admin updates, governance) must call this afterwards.
+    pub fn validate_config(&self) -> Result<()> {
+        require!(
+            self.liquidation_threshold > Bps::ZERO && self.liquidation_threshold <= Bps::ONE,
+            LendingError::InvalidLiquidationThreshold
+        );
+        require!(self.max_ltv > Bps::ZERO, LendingError::InvalidMaxLtv);
+        require!(self.max_ltv <= self.liquidation_threshold, LendingError::MaxLtvAboveLiquidationThreshold);
+        require!(self.liquidation_bonus <= MAX_LIQUIDATION_BONUS, LendingError::InvalidLiquidationBonus);
+        require!(
+            self.liquidation_close_factor > Bps::ZERO && self.liquidation_close_factor <= Bps::ONE,
+            LendingError::InvalidCloseFactor
+        );
+        require!(
+            self.base_rate <= MAX_RATE && self.multiplier <= MAX_RATE && self.jump_multiplier <= MAX_RATE,
+            LendingError::InvalidInterestRate
+        );
+        require!(
+            self.kink_utilization > Bps::ZERO && self.kink_utilization <= Bps::ONE,
+            LendingError::InvalidKinkUtilization
+        );
+        require!(self.reserve_factor <= Bps::ONE, LendingError::InvalidReserveFactor);
+        require!(self.min_liquidity_buffer <= Bps::ONE, LendingError::InvalidLiquidityBuffer);
+        require!(self.origination_fee <= MAX_ORIGINATION_FEE, LendingError::InvalidOriginationFee);
+        require!(self.flash_loan_fee <= MAX_FLASH_LOAN_FEE, LendingError::InvalidFlashLoanFee);
+        require!(self.flash_loan_protocol_share <= Bps::ONE, LendingError::InvalidFlashLoanProtocolShare);
+        require!(self.max_price_age > 0, LendingError::InvalidMaxPriceAge);
+        require!(
+            self.secondary_oracle != SecondaryOracle::PythFeed || self.secondary_feed_id != [0; 32],
+            LendingError::SecondaryFeedNotSet
+        );
+        require!(
+            self.circuit_breaker_window >= 0 && self.circuit_breaker_cooldown >= 0,
+            LendingError::InvalidCircuitBreakerConfig
+        );
+        if self.stake_pool != Pubkey::default() {
+            require!(
+                self.min_stake_pool_rate > Bps::ZERO && self.min_stake_pool_rate <= self.max_stake_pool_rate,
+                LendingError::InvalidStakePoolRateBounds
+            );
+        }
+        Ok(())
+    }
+
+    /// Rejects instructions that would touch the bank's balances while a flash loan is outstanding,
+    /// since the treasury is drained but the bank totals don't show it.
+    pub fn require_no_active_flash_loan(&self) -> Result<()> {
+        require!(!self.flash_loan_active, LendingError::FlashLoanActive);
+        Ok(())
+    }
+
+    /// Treasury tokens that may leave the bank: the balance minus what belongs to protocol reserves.
+    pub fn available_liquidity(&self, treasury_balance: u64) -> u64 {
+        treasury_balance.saturating_sub(self.protocol_reserves)
+    }
+
+    /// Liquidity a new borrow may draw, leaving the minimum buffer in place for withdrawals.
+    pub fn borrowable_liquidity(&self, treasury_balance: u64) -> Option<u64> {
+        let buffer = self.min_liquidity_buffer.apply(self.total_deposits)?;
+        Some(self.available_liquidity(treasury_balance).saturating_sub(buffer))
+    }
+
+    /// Writes a verified oracle price into the cache and folds it into the TWAP. The jump
+    /// flag compares the new price against the TWAP as it stood before this update.
+    pub fn record_price(&mut self, price: i64, conf: u64, exponent: i32, publish_time: i64) -> Result<()> {
+        require!(price > 0, LendingError::OracleError);
+        require!(publish_time >= self.price_publish_time, LendingError::StalePrice);
+
+        // Moves are measured from the start of the window, so a run of small steps adds up. Once
+        // the window has passed, the last cached price starts the next one.
+        if exponent != self.price_exponent {
+            self.circuit_breaker_reference_price = price;
+            self.circuit_breaker_reference_time = publish_time;
+        } else if publish_time - self.circuit_breaker_reference_time > self.circuit_breaker_window {
+            self.circuit_breaker_reference_price = self.price;
+            self.circuit_breaker_reference_time = self.price_publish_time;
+        }
+        let within_window = publish_time - self.circuit_breaker_reference_time <= self.circuit_breaker_window;
+        if self.circuit_breaker_reference_price > 0
+            && within_window
+            && self.circuit_breaker_threshold > Bps::ZERO
+            && price_deviation(price, self.circuit_breaker_reference_price) > self.circuit_breaker_threshold
+        {
+            self.circuit_breaker_tripped_at = publish_time;
+        }
+
+        if self.twap_price == 0 || exponent != self.price_exponent {
+            self.twap_price = price;
+            self.price_jump_flagged = false;
+        } else {
+            let previous_twap = self.twap_price;
+            let elapsed = (publish_time - self.price_publish_time).min(TWAP_WINDOW) as i128;
+            let twap = previous_twap as i128 + (price as i128 - previous_twap as i128) * elapsed / TWAP_WINDOW as i128;
+            self.twap_price = twap as i64;
+            self.price_jump_flagged = price_deviation(price, previous_twap) > self.max_twap_deviation;
+        }
+
+        self.price = price;
+        self.price_conf = conf;
+        self.price_exponent = exponent;
+        self.price_publish_time = publish_time;
+        Ok(())
+    }
+
+    /// Converts a SOL/USD price (or confidence) into this bank's LST price using the stake
+    /// pool's exchange rate. The pool must have been updated this epoch and its rate must lie
+    /// within the bank's bounds, so a stale or manipulated pool cannot move the price.
+    pub fn stake_pool_price(&self, sol_price: i64, pool: &StakePoolRate, epoch: u64) -> Result<i64> {
+        require_keys_eq!(pool.pool_mint, self.mint_address, LendingError::InvalidStakePool);
+        require!(pool.last_update_epoch == epoch, LendingError::StakePoolNotUpdated);
+        let rate = pool.rate().ok_or(LendingError::InvalidStakePool)?;
+        require!(
+            rate >= self.min_stake_pool_rate && rate <= self.max_stake_pool_rate,
+            LendingError::StakePoolRateOutOfBounds
+        );
+        let price = sol_price as i128 * pool.total_lamports as i128 / pool.pool_token_supply as i128;
+        i64::try_from(price).map_err(|_| error!(LendingError::MathOverflow))
+    }
+
+    /// Fails while the circuit breaker is tripped and its cooldown has not yet run out.
+    pub fn require_circuit_breaker_closed(&self, now: i64) -> Result<()> {
+        let tripped = self.circuit_breaker_tripped_at != 0
+            && now < self.circuit_breaker_tripped_at.saturating_add(self.circuit_breaker_cooldown);
+        require!(!tripped, LendingError::CircuitBreakerTripped);
+        Ok(())
+    }
+
+    pub fn record_secondary_price(&mut self, price: i64, exponent: i32, publish_time: i64) -> Result<()> {
+        require!(price > 0, LendingError::OracleError);
+        require!(publish_time >= self.secondary_price_publish_time, LendingError::StalePrice);
+        self.secondary_price = price;
+        self.secondary_price_exponent = exponent;
+        self.secondary_price_publish_time = publish_time;
+        Ok(())
+    }
+
+    /// Price used to value this bank's asset. The cached primary price is used while fresh;
+    /// when stale, the configured secondary source takes over. When both are fresh they must
+    /// agree within `max_oracle_deviation`.
+    pub fn oracle_price(&self, now: i64) -> Result<OraclePrice> {
+        require!(self.oracle_feed_id != [0; 32], LendingError::OracleNotConfigured);
+        match (self.primary_price(now), self.fallback_price(now)?) {
+            (Some(primary), Some(secondary)) => {
+                require!(
+                    price_deviation(secondary.price, primary.price) <= self.max_oracle_deviation,
+                    LendingError::OracleDeviation
+                );
+                Ok(primary)
+            }
+            (Some(primary), None) => Ok(primary),
+            (None, Some(secondary)) => Ok(secondary),
+            (None, None) => err!(LendingError::StalePrice),
+        }
+    }
+
+    fn primary_price(&self, now: i64) -> Option<OraclePrice> {
+        let fresh = self.price > 0 && now.saturating_sub(self.price_publish_time) <= self.max_price_age as i64;
+        fresh.then_some(OraclePrice {
+            price: self.price,
+            exponent: self.price_exponent,
+            source: PriceSource::Primary,
+        })
+    }
+
+    // Secondary prices are expressed in the primary feed's exponent, so both compare directly
+    fn fallback_price(&self, now: i64) -> Result<Option<OraclePrice>> {
+        if self.price <= 0 {
+            return Ok(None);
+        }
+        let (price, source) = match self.secondary_oracle {
+            SecondaryOracle::None => return Ok(None),
+            SecondaryOracle::PythFeed => {
+                let age = now.saturating_sub(self.secondary_price_publish_time);
+                if self.secondary_price <= 0 || age > self.max_price_age as i64 {
+                    return Ok(None);
+                }
+                let price = rescale_price(self.secondary_price, self.secondary_price_exponent, self.price_exponent)
+                    .ok_or(LendingError::MathOverflow)?;
+                (price, PriceSource::SecondaryFeed)
+            }
+            SecondaryOracle::Twap => {
+                // The TWAP smooths over one window, so it stays usable that much longer than spot
+                let age = now.saturating_sub(self.price_publish_time);
+                if age > self.max_price_age as i64 + TWAP_WINDOW {
+                    return Ok(None);
+                }
+                (self.twap_price, PriceSource::Twap)
+            }
+        };
+        Ok(Some(OraclePrice { price, exponent: self.price_exponent, source }))
+    }
+
+    pub fn calculate_flash_loan_fee(&self, amount: u64, exempt: bool) -> Result<u64> {
+        if exempt {
+            return Ok(0);
+        }
+        Ok(self.flash_loan_fee.apply(amount).ok_or(LendingError::MathOverflow)?)
+    }
+
+    /// Moves the share of uncollected origination fees paid back by a repayment into protocol
+    /// reserves, pro rata to the debt repaid. Call before `total_borrowed` is reduced.
+    pub fn collect_origination_fees(&mut self, repaid: u64) -> Result<u64> {
+        let collected = if repaid >= self.total_borrowed {
+            self.uncollected_origination_fees
+        } else {
+            (self.uncollected_origination_fees as u128 * repaid as u128 / self.total_borrowed as u128) as u64
+        };
+        self.uncollected_origination_fees -= collected;
+        self.protocol_reserves = self.protocol_reserves
+            .checked_add(collected)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(collected)
+    }
+
+    /// Splits a repaid flash loan fee between depositors and protocol reserves.
+    pub fn credit_flash_loan_fee(&mut self, fee: u64) -> Result<()> {
+        let to_reserves = self.flash_loan_protocol_share.apply(fee).ok_or(LendingError::MathOverflow)?;
+        self.protocol_reserves = self.protocol_reserves
+            .checked_add(to_reserves)
+            .ok_or(LendingError::MathOverflow)?;
+        self.total_deposits = self.total_deposits
+            .checked_add(fee - to_reserves)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
     }
 }
 
-pub fn calculate_accrued_interest(principal: u64, annual_rate: u64, last_updated: i64) -> Result<u64> {
-    let current_time = Clock::get()?.unix_timestamp;
-    let time_diff = current_time - last_updated;
-    if time_diff <= 0 {
-        return Ok(principal);
+impl VoteLock {
+    // Power decays linearly to zero at lock_end; a max-duration lock starts at 1 vote per token
+    pub fn voting_power(&self, now: i64) -> u64 {
+        if now >= self.lock_end {
+            return 0;
+        }
+        let remaining = (self.lock_end - now).min(MAX_LOCK_DURATION) as u128;
+        (self.amount as u128 * remaining / MAX_LOCK_DURATION as u128) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bps_applies_as_fraction_of_ten_thousand() {
+        // Default liquidation bonus (500) is 5%, default close factor (5000) is 50%
+        assert_eq!(Bps(500).apply(10_000), Some(500));
+        assert_eq!(Bps(5000).apply(1_000), Some(500));
+        assert_eq!(Bps::ONE.apply(u64::MAX), Some(u64::MAX));
+        // Rounds down
+        assert_eq!(Bps(1).apply(9_999), Some(0));
     }
-    let rate_per_second = annual_rate as f64 / BASIS_POINTS as f64 / SECONDS_PER_YEAR as f64;
-    let new_value = (principal as f64 * E.powf(rate_per_second * time_diff as f64)) as u64;
-    Ok(new_value)
-}
\ No newline at end of file
+
+    #[test]
+    fn bps_apply_u128_handles_position_values() {
+        let value = u64::MAX as u128 * 100;
+        assert_eq!(Bps(7500).apply_u128(value), Some(value * 3 / 4));
+        assert_eq!(Bps(2).apply_u128(u128::MAX), None);
+    }
+
+    #[test]
+    fn bps_complement() {
+        assert_eq!(Bps(1000).complement(), Some(Bps(9000)));
+        assert_eq!(Bps::ONE.complement(), Some(Bps::ZERO));
+        assert_eq!(Bps(10_001).complement(), None);
+    }
+
+    #[test]
+    fn health_factor_uses_threshold_in_bps() {
+        // 100 collateral at an 80% threshold supports exactly 80 debt
+        let weighted = Bps(8000).apply_u128(100).unwrap();
+        assert_eq!(health_factor(weighted, 80), Ratio::ONE);
+        assert!(health_factor(weighted, 81) < Ratio::ONE);
+        assert!(health_factor(weighted, 79) > Ratio::ONE);
+        assert_eq!(health_factor(weighted, 0), Ratio::MAX);
+    }
+
+    #[test]
+    fn ratio_from_fraction() {
+        assert_eq!(Ratio::from_fraction(1, 2), Ratio(Ratio::ONE.0 / 2));
+        assert_eq!(Ratio::from_fraction(u64::MAX as u128, 1), Ratio(u64::MAX as u128 * Ratio::ONE.0));
+        // Values far past u64 keep their fractional part
+        let debt = 1_000_000 * USD;
+        assert_eq!(Ratio::from_fraction(debt * 3 / 2, debt), Ratio(Ratio::ONE.0 * 3 / 2));
+    }
+
+    #[test]
+    fn liquidation_at_realistic_pyth_magnitudes() {
+        // $10,000 of SOL collateral at $150 against $9,000 of USDC debt: health 0.8 * 10k / 9k < 1
+        let sol_bank = Bank {
+            mint_decimals: 9,
+            liquidation_threshold: Bps(8000),
+            liquidation_bonus: Bps(500),
+            total_deposits: 66_666_666_667,
+            total_deposits_shares: 66_666_666_667,
+            ..Default::default()
+        };
+        let usdc_bank = Bank {
+            mint_decimals: 6,
+            liquidation_close_factor: Bps(5000),
+            total_borrowed: 9_000_000_000,
+            total_borrowed_shares: 9_000_000_000,
+            ..Default::default()
+        };
+        let user = User {
+            deposited_sol_shares: 66_666_666_667,
+            borrowed_usdc_shares: 9_000_000_000,
+            ..Default::default()
+        };
+        let (sol_price, usdc_price) = (pyth_price(150.0), pyth_price(1.0));
+        let position = position_value(&user, &usdc_bank, usdc_price, &sol_bank, sol_price).unwrap();
+        assert!(health_factor(position.liquidation_collateral, position.debt) < Ratio::ONE);
+
+        // Half the debt, 4,500 USDC, is repaid for $4,725 of SOL: 31.5 SOL
+        let (repaid, seized) =
+            liquidation_amounts(&usdc_bank, 9_000_000_000, usdc_price, &sol_bank, 66_666_666_667, sol_price).unwrap();
+        assert_eq!(repaid, 4_500_000_000);
+        assert_eq!(seized, 31_500_000_000);
+
+        // With too little collateral all of it is seized and the repayment scales down to match
+        let (repaid, seized) =
+            liquidation_amounts(&usdc_bank, 9_000_000_000, usdc_price, &sol_bank, 21_000_000_000, sol_price).unwrap();
+        assert_eq!(seized, 21_000_000_000);
+        assert_eq!(repaid, 3_000_000_000);
+        assert_eq!(value_to_tokens(token_value(seized, 9, sol_price).unwrap(), 9, sol_price, Rounding::Down).unwrap(), seized);
+    }
+
+    #[test]
+    fn twap_follows_spot_and_flags_jumps() {
+        let mut bank = Bank { max_twap_deviation: Bps(1000), ..Default::default() };
+        bank.record_price(100_000, 10, -3, 1_000).unwrap();
+        assert_eq!(bank.twap_price, 100_000);
+        assert!(!bank.price_jump_flagged);
+
+        // Half a window later the EMA has moved halfway to the new spot
+        bank.record_price(104_000, 10, -3, 1_000 + TWAP_WINDOW / 2).unwrap();
+        assert_eq!(bank.twap_price, 102_000);
+        assert!(!bank.price_jump_flagged);
+
+        // A 20% move against the TWAP is flagged
+        bank.record_price(122_400, 10, -3, 1_000 + TWAP_WINDOW).unwrap();
+        assert!(bank.price_jump_flagged);
+
+        // Updates older than the cached one are rejected
+        assert!(bank.record_price(100_000, 10, -3, 1_000).is_err());
+        assert_eq!(price_deviation(90, 100), Bps(1000));
+    }
+
+    const LST_MINT: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    /// Serialized `spl_stake_pool::state::StakePool` prefix, up to last_update_epoch, with
+    /// trailing bytes standing in for the fields after it.
+    fn stake_pool_account(total_lamports: u64, pool_token_supply: u64, last_update_epoch: u64) -> Vec<u8> {
+        let mut data = vec![1u8];
+        data.extend_from_slice(&[0; 32 * 3]);
+        data.push(255);
+        data.extend_from_slice(&[0; 32 * 2]);
+        data.extend_from_slice(LST_MINT.as_ref());
+        data.extend_from_slice(&[0; 32 * 2]);
+        data.extend_from_slice(&total_lamports.to_le_bytes());
+        data.extend_from_slice(&pool_token_supply.to_le_bytes());
+        data.extend_from_slice(&last_update_epoch.to_le_bytes());
+        data.extend_from_slice(&[0; 64]);
+        data
+    }
+
+    #[test]
+    fn lst_price_follows_stake_pool_rate_within_bounds() {
+        let bank = Bank {
+            mint_address: LST_MINT,
+            stake_pool: Pubkey::new_unique(),
+            min_stake_pool_rate: Bps::ONE,
+            max_stake_pool_rate: Bps(20000),
+            ..Default::default()
+        };
+        let pool = StakePoolRate::try_from_account_data(&stake_pool_account(1_150_000_000, 1_000_000_000, 500)).unwrap();
+        assert_eq!(pool.rate(), Some(Bps(11500)));
+
+        // 1.15 SOL per LST at $150 per SOL
+        assert_eq!(bank.stake_pool_price(15_000_000_000, &pool, 500).unwrap(), 17_250_000_000);
+
+        // The pool must have been updated this epoch
+        assert!(bank.stake_pool_price(15_000_000_000, &pool, 501).is_err());
+
+        // and must issue the bank's own mint
+        let other_mint = Bank { mint_address: Pubkey::new_unique(), ..bank };
+        assert!(other_mint.stake_pool_price(15_000_000_000, &pool, 500).is_err());
+
+        // Rates outside the bounds, such as an inflated pool, are rejected
+        let inflated = StakePoolRate::try_from_account_data(&stake_pool_account(3_000_000_000, 1_000_000_000, 500)).unwrap();
+        assert!(bank.stake_pool_price(15_000_000_000, &inflated, 500).is_err());
+        let empty = StakePoolRate::try_from_account_data(&stake_pool_account(0, 0, 500)).unwrap();
+        assert!(bank.stake_pool_price(15_000_000_000, &empty, 500).is_err());
+
+        // Accounts that are not an initialised stake pool, or are truncated, fail to parse
+        let mut uninitialized = stake_pool_account(1, 1, 500);
+        uninitialized[0] = 0;
+        assert!(StakePoolRate::try_from_account_data(&uninitialized).is_err());
+        assert!(StakePoolRate::try_from_account_data(&stake_pool_account(1, 1, 500)[..270]).is_err());
+    }
+
+    #[test]
+    fn lst_deposit_counts_as_collateral_at_the_stake_pool_price() {
+        // 2 LST at 1.15 SOL each and $150 per SOL ($345) back 100 USDC of debt
+        let lst_bank = Bank {
+            mint_address: LST_MINT,
+            mint_decimals: 9,
+            stake_pool: Pubkey::new_unique(),
+            min_stake_pool_rate: Bps::ONE,
+            max_stake_pool_rate: Bps(20000),
+            max_ltv: Bps(6000),
+            liquidation_threshold: Bps(7000),
+            total_deposits: 2_000_000_000,
+            total_deposits_shares: 2_000_000_000,
+            ..Default::default()
+        };
+        let usdc_bank = Bank {
+            mint_decimals: 6,
+            total_borrowed: 100_000_000,
+            total_borrowed_shares: 100_000_000,
+            ..Default::default()
+        };
+        let user = User {
+            sol_address: LST_MINT,
+            deposited_sol_shares: 2_000_000_000,
+            borrowed_usdc_shares: 100_000_000,
+            ..Default::default()
+        };
+        let pool = StakePoolRate::try_from_account_data(&stake_pool_account(1_150_000_000, 1_000_000_000, 500)).unwrap();
+        let lst_price = OraclePrice {
+            price: lst_bank.stake_pool_price(15_000_000_000, &pool, 500).unwrap(),
+            exponent: -8,
+            source: PriceSource::Primary,
+        };
+        let position = position_value(&user, &usdc_bank, pyth_price(1.0), &lst_bank, lst_price).unwrap();
+        assert_eq!(position.borrow_power, 207 * USD);
+        assert_eq!(position.liquidation_collateral, 2415 * USD / 10);
+        assert_eq!(position.debt, 100 * USD);
+    }
+
+    #[test]
+    fn position_value_prices_each_asset_at_its_own_bank() {
+        // 1000 USDC deposited against 5 SOL borrowed
+        let usdc_bank = Bank {
+            mint_decimals: 6,
+            max_ltv: Bps(8000),
+            liquidation_threshold: Bps(8500),
+            total_deposits: 1_000_000_000,
+            total_deposits_shares: 1_000_000_000,
+            ..Default::default()
+        };
+        let sol_bank = Bank {
+            mint_decimals: 9,
+            total_borrowed: 5_000_000_000,
+            total_borrowed_shares: 5_000_000_000,
+            ..Default::default()
+        };
+        let user = User {
+            deposited_usdc_shares: 1_000_000_000,
+            borrowed_sol_shares: 5_000_000_000,
+            ..Default::default()
+        };
+        let position = position_value(&user, &usdc_bank, pyth_price(1.0), &sol_bank, pyth_price(150.0)).unwrap();
+        assert_eq!(position.borrow_power, 800 * USD);
+        assert_eq!(position.liquidation_collateral, 850 * USD);
+        assert_eq!(position.debt, 750 * USD);
+        assert!(position_value(&user, &usdc_bank, pyth_price(1.0), &sol_bank, pyth_price(0.0)).is_err());
+    }
+
+    #[test]
+    fn withdraw_removes_borrow_power_at_the_withdrawn_assets_own_ltv() {
+        // 1000 USDC at 80% LTV and 10 SOL at $150 and 50% LTV against 1000 USDC of debt
+        let usdc_bank = Bank {
+            mint_decimals: 6,
+            max_ltv: Bps(8000),
+            liquidation_threshold: Bps(8500),
+            total_deposits: 1_000_000_000,
+            total_deposits_shares: 1_000_000_000,
+            total_borrowed: 1_000_000_000,
+            total_borrowed_shares: 1_000_000_000,
+            ..Default::default()
+        };
+        let sol_bank = Bank {
+            mint_decimals: 9,
+            max_ltv: Bps(5000),
+            liquidation_threshold: Bps(6000),
+            total_deposits: 10_000_000_000,
+            total_deposits_shares: 10_000_000_000,
+            ..Default::default()
+        };
+        let user = User {
+            deposited_usdc_shares: 1_000_000_000,
+            deposited_sol_shares: 10_000_000_000,
+            borrowed_usdc_shares: 1_000_000_000,
+            ..Default::default()
+        };
+        let position = position_value(&user, &usdc_bank, pyth_price(1.0), &sol_bank, pyth_price(150.0)).unwrap();
+        assert_eq!(position.borrow_power, 1_550 * USD);
+
+        // Withdrawing 5 SOL ($750) gives up $375 of power at SOL's 50%, not $600 at USDC's 80%
+        let after_sol = position.borrow_power_after_withdraw(&sol_bank, 5_000_000_000, pyth_price(150.0)).unwrap();
+        assert_eq!(after_sol, 1_175 * USD);
+        assert!(after_sol >= position.debt);
+
+        // Withdrawing 750 USDC gives up $600 at USDC's 80%, leaving too little for the debt
+        let after_usdc = position.borrow_power_after_withdraw(&usdc_bank, 750_000_000, pyth_price(1.0)).unwrap();
+        assert_eq!(after_usdc, 950 * USD);
+        assert!(after_usdc < position.debt);
+    }
+
+    const USD: u128 = 10u128.pow(VALUE_DECIMALS as u32);
+
+    // A Pyth price at the usual exponent of -8
+    fn pyth_price(usd: f64) -> OraclePrice {
+        OraclePrice { price: (usd * 1e8) as i64, exponent: -8, source: PriceSource::Primary }
+    }
+
+    #[test]
+    fn token_value_normalizes_mint_decimals_and_exponent() {
+        // 1 SOL (9 decimals) at $150 is worth the same as 150 USDC (6 decimals) at $1
+        let sol = token_value(1_000_000_000, 9, pyth_price(150.0)).unwrap();
+        let usdc = token_value(150_000_000, 6, pyth_price(1.0)).unwrap();
+        assert_eq!(sol, 150 * USD);
+        assert_eq!(sol, usdc);
+
+        // The same price quoted at another exponent values identically
+        let sol_exp_5 = OraclePrice { price: 15_000_000, exponent: -5, source: PriceSource::Primary };
+        assert_eq!(token_value(1_000_000_000, 9, sol_exp_5).unwrap(), sol);
+
+        // Realistic whale balances stay in range
+        assert!(token_value(u64::MAX, 9, pyth_price(10_000.0)).is_ok());
+    }
+
+    #[test]
+    fn circuit_breaker_trips_on_fast_moves_and_cools_down() {
+        let mut bank = Bank {
+            circuit_breaker_threshold: Bps(2000),
+            circuit_breaker_window: 300,
+            circuit_breaker_cooldown: 1800,
+            ..Default::default()
+        };
+        bank.record_price(100_000, 10, -3, 1_000).unwrap();
+
+        // A 25% move spread over longer than the window does not trip it
+        bank.record_price(125_000, 10, -3, 1_400).unwrap();
+        assert!(bank.require_circuit_breaker_closed(1_400).is_ok());
+
+        // A 24% drop within the window does
+        bank.record_price(95_000, 10, -3, 1_500).unwrap();
+        assert_eq!(bank.circuit_breaker_tripped_at, 1_500);
+        assert!(bank.require_circuit_breaker_closed(1_500 + 1_799).is_err());
+        assert!(bank.require_circuit_breaker_closed(1_500 + 1_800).is_ok());
+    }
+
+    #[test]
+    fn circuit_breaker_adds_up_small_steps_within_the_window() {
+        let mut bank = Bank {
+            circuit_breaker_threshold: Bps(2000),
+            circuit_breaker_window: 300,
+            circuit_breaker_cooldown: 1800,
+            ..Default::default()
+        };
+        bank.record_price(100_000, 10, -3, 1_000).unwrap();
+
+        // Each step is under 10% but together they are 25% above the window's starting price
+        bank.record_price(108_000, 10, -3, 1_100).unwrap();
+        bank.record_price(116_000, 10, -3, 1_200).unwrap();
+        assert_eq!(bank.circuit_breaker_tripped_at, 0);
+        bank.record_price(125_000, 10, -3, 1_290).unwrap();
+        assert_eq!(bank.circuit_breaker_tripped_at, 1_290);
+        assert_eq!(bank.circuit_breaker_reference_price, 100_000);
+
+        // The same steps spread past the window each start from a newer reference
+        let mut bank = Bank { circuit_breaker_threshold: Bps(2000), circuit_breaker_window: 300, ..Default::default() };
+        bank.record_price(100_000, 10, -3, 1_000).unwrap();
+        bank.record_price(108_000, 10, -3, 1_200).unwrap();
+        bank.record_price(116_000, 10, -3, 1_400).unwrap();
+        bank.record_price(125_000, 10, -3, 1_600).unwrap();
+        assert_eq!(bank.circuit_breaker_tripped_at, 0);
+    }
+
+    #[test]
+    fn secondary_feed_takes_over_when_primary_is_stale() {
+        let mut bank = Bank {
+            oracle_feed_id: [1; 32],
+            secondary_oracle: SecondaryOracle::PythFeed,
+            secondary_feed_id: [2; 32],
+            max_price_age: 60,
+            max_oracle_deviation: Bps(500),
+            ..Default::default()
+        };
+        bank.record_price(100_000, 10, -3, 1_000).unwrap();
+        // Same price quoted with one more decimal
+        bank.record_secondary_price(1_010_000, -4, 1_000).unwrap();
+
+        let price = bank.oracle_price(1_030).unwrap();
+        assert_eq!((price.price, price.source), (100_000, PriceSource::Primary));
+
+        // Primary stale: the secondary is used, rescaled to the primary exponent
+        bank.record_secondary_price(1_020_000, -4, 1_070).unwrap();
+        let price = bank.oracle_price(1_100).unwrap();
+        assert_eq!((price.price, price.exponent, price.source), (102_000, -3, PriceSource::SecondaryFeed));
+
+        // Both fresh but 10% apart
+        bank.record_price(112_200, 10, -3, 1_100).unwrap();
+        assert!(bank.oracle_price(1_100).is_err());
+
+        // Nothing fresh
+        assert!(bank.oracle_price(1_200).is_err());
+
+        // The TWAP outlives spot by one window
+        bank.secondary_oracle = SecondaryOracle::Twap;
+        let price = bank.oracle_price(1_200).unwrap();
+        assert_eq!(price.source, PriceSource::Twap);
+        assert!(bank.oracle_price(1_100 + 60 + TWAP_WINDOW + 1).is_err());
+    }
+
+    /// Minimal pool model driven through the same share conversions as the handlers.
+    #[derive(Clone, Copy, Debug)]
+    struct Pool {
+        amount: u64,
+        shares: u64,
+    }
+
+    impl Pool {
+        // amount / shares, compared by cross-multiplication
+        fn value_per_share_ge(&self, other: &Pool) -> bool {
+            let (amount, shares) = self.with_virtual();
+            let (other_amount, other_shares) = other.with_virtual();
+            amount * other_shares >= other_amount * shares
+        }
+
+        fn with_virtual(&self) -> (u128, u128) {
+            (
+                self.amount as u128 + VIRTUAL_ASSETS as u128,
+                self.shares as u128 + VIRTUAL_SHARES as u128,
+            )
+        }
+
+        fn mint(&mut self, amount: u64, rounding: Rounding) -> Option<u64> {
+            let shares = amount_to_shares(amount, self.amount, self.shares, rounding)?;
+            self.amount = self.amount.checked_add(amount)?;
+            self.shares = self.shares.checked_add(shares)?;
+            Some(shares)
+        }
+
+        fn burn(&mut self, amount: u64, rounding: Rounding) -> Option<u64> {
+            let shares = amount_to_shares(amount, self.amount, self.shares, rounding)?;
+            self.amount = self.amount.checked_sub(amount)?;
+            self.shares = self.shares.checked_sub(shares)?;
+            Some(shares)
+        }
+    }
+
+    #[test]
+    fn origination_fees_reach_reserves_only_as_debt_is_repaid() {
+        // 1000 borrowed plus a 10 fee, still owed
+        let mut bank = Bank { total_borrowed: 1_010, uncollected_origination_fees: 10, ..Default::default() };
+        assert_eq!(bank.collect_origination_fees(505).unwrap(), 5);
+        assert_eq!((bank.protocol_reserves, bank.uncollected_origination_fees), (5, 5));
+
+        bank.total_borrowed -= 505;
+        assert_eq!(bank.collect_origination_fees(505).unwrap(), 5);
+        assert_eq!((bank.protocol_reserves, bank.uncollected_origination_fees), (10, 0));
+    }
+
+    #[test]
+    fn debt_shares_round_against_the_borrower() {
+        // 3 debt over 2 shares: borrowing 1 mints ceil(2/3) = 1, repaying 1 burns floor(2/3) = 0
+        assert_eq!(amount_to_shares(1, 3, 2, Rounding::Up), Some(1));
+        assert_eq!(amount_to_shares(1, 3, 2, Rounding::Down), Some(0));
+        // Empty pools mint 1:1
+        assert_eq!(amount_to_shares(42, 0, 0, Rounding::Up), Some(42));
+        assert_eq!(amount_to_shares(u64::MAX, 1, u64::MAX, Rounding::Down), None);
+    }
+
+    #[test]
+    fn first_depositor_inflation_attack_is_unprofitable() {
+        let attacker_deposit = 1;
+        let donation = 10_000;
+        let victim_deposit = 10_000;
+
+        // Without offsets: one share, then inflate total_deposits so the victim rounds to zero
+        let naive_shares = |amount: u64, total: u64, shares: u64| {
+            if total == 0 { amount } else { amount * shares / total }
+        };
+        let mut pool = Pool { amount: 0, shares: 0 };
+        let attacker_shares = naive_shares(attacker_deposit, pool.amount, pool.shares);
+        pool = Pool { amount: attacker_deposit, shares: attacker_shares };
+        pool.amount += donation;
+        let victim_shares = naive_shares(victim_deposit, pool.amount, pool.shares);
+        assert_eq!(victim_shares, 0);
+        pool.amount += victim_deposit;
+        // The attacker's single share now redeems the whole pool, victim deposit included
+        assert_eq!(pool.amount * attacker_shares / pool.shares, attacker_deposit + donation + victim_deposit);
+
+        // With virtual offsets the same sequence leaves the victim whole
+        let mut pool = Pool { amount: 0, shares: 0 };
+        let attacker_shares = pool.mint(attacker_deposit, Rounding::Down).unwrap();
+        assert_eq!(attacker_shares, attacker_deposit);
+        pool.amount += donation;
+        let victim_shares = pool.mint(victim_deposit, Rounding::Down).unwrap();
+        assert!(victim_shares > 0);
+
+        let victim_redeemable = shares_to_amount(victim_shares, pool.amount, pool.shares, Rounding::Down).unwrap();
+        let attacker_redeemable = shares_to_amount(attacker_shares, pool.amount, pool.shares, Rounding::Down).unwrap();
+        assert!(victim_redeemable + 1 >= victim_deposit);
+        assert!(attacker_redeemable < attacker_deposit + donation);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn deposit_share_value_never_decreases(
+            initial in 1u64..1_000_000_000_000,
+            interest in 0u64..1_000_000_000,
+            ops in proptest::collection::vec((proptest::bool::ANY, 1u64..1_000_000_000), 1..32),
+        ) {
+            let mut pool = Pool { amount: initial, shares: initial };
+            // Interest accrual raises total_deposits without minting shares
+            pool.amount += interest;
+            for (is_deposit, amount) in ops {
+                let before = pool;
+                let result = if is_deposit {
+                    pool.mint(amount, Rounding::Down)
+                } else {
+                    pool.burn(amount, Rounding::Up)
+                };
+                if result.is_none() {
+                    pool = before;
+                    continue;
+                }
+                proptest::prop_assert!(pool.value_per_share_ge(&before));
+            }
+        }
+
+        #[test]
+        fn debt_shares_never_undercount_what_is_owed(
+            initial in 1u64..1_000_000_000_000,
+            interest in 0u64..1_000_000_000,
+            ops in proptest::collection::vec((proptest::bool::ANY, 1u64..1_000_000_000), 1..32),
+        ) {
+            let mut pool = Pool { amount: initial, shares: initial };
+            pool.amount += interest;
+            for (is_borrow, amount) in ops {
+                let before = pool;
+                if is_borrow {
+                    let Some(minted) = pool.mint(amount, Rounding::Up) else {
+                        pool = before;
+                        continue;
+                    };
+                    // Minted debt shares are worth at least what was borrowed
+                    let (total, shares) = pool.with_virtual();
+                    proptest::prop_assert!(minted as u128 * total >= amount as u128 * shares);
+                } else {
+                    let Some(burned) = pool.burn(amount, Rounding::Down) else {
+                        pool = before;
+                        continue;
+                    };
+                    // Burned debt shares are worth at most what was repaid
+                    let (total, shares) = before.with_virtual();
+                    proptest::prop_assert!(burned as u128 * total <= amount as u128 * shares);
+                }
+            }
+        }
+    }
+}